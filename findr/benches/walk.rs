@@ -0,0 +1,57 @@
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use std::{
+    fs,
+    path::Path,
+    process::{Command, Stdio},
+};
+use tempfile::TempDir;
+
+const PRG: &str = env!("CARGO_BIN_EXE_findr");
+
+// Builds a tree of 4,096 directories with 8 files each.
+fn gen_tree() -> TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    for path in (0..8)
+        .flat_map(|a| (0..8).map(move |b| (a, b)))
+        .flat_map(|(a, b)| (0..64).map(move |c| format!("{}/{}/{}", a, b, c)))
+    {
+        let path = dir.path().join(path);
+        fs::create_dir_all(&path).unwrap();
+        for i in 0..8 {
+            fs::write(path.join(format!("{}.txt", i)), i.to_string()).unwrap();
+        }
+    }
+    dir
+}
+
+fn findr(root: &Path, args: &[&str]) {
+    let status = Command::new(PRG)
+        .arg(root)
+        .args(args)
+        .stdout(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+fn bench_walk(c: &mut Criterion) {
+    let tree = gen_tree();
+    let mut group = c.benchmark_group("walk");
+    group.sample_size(10);
+
+    for threads in ["1", "2", "4", "8"] {
+        group.bench_with_input(BenchmarkId::new("print", threads), threads, |b, t| {
+            b.iter(|| findr(tree.path(), &["-j", t]))
+        });
+        group.bench_with_input(BenchmarkId::new("size", threads), threads, |b, t| {
+            b.iter(|| findr(tree.path(), &["-j", t, "-size", "+0"]))
+        });
+        group.bench_with_input(BenchmarkId::new("sort_name", threads), threads, |b, t| {
+            b.iter(|| findr(tree.path(), &["-j", t, "--sort", "name"]))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_walk);
+criterion_main!(benches);
@@ -0,0 +1,652 @@
+use std::{
+    borrow::Cow,
+    ffi::{OsStr, OsString},
+    fmt::Display,
+    fs,
+    io::{self, Write},
+    path::{Component, Path, PathBuf},
+    process::Command,
+    time::SystemTime,
+};
+
+use chrono::{DateTime, Local};
+
+use crate::{
+    EntryType,
+    predicate::{TimeField, entry_mode, entry_owner, entry_time, meta_mode, meta_time},
+    walk::Entry,
+};
+
+// Upper bound on the number of paths passed to one `-exec ... +` command.
+const MAX_BATCH: usize = 1024;
+
+/// An expression primary with a side effect. Actions are always true except
+/// `-exec ... ;`, which is true when the command exits successfully, and
+/// `-delete`, which is true when the entry was removed.
+#[derive(Debug, Clone)]
+pub enum Action {
+    Print,
+    Print0,
+    Printf(Vec<Directive>),
+    Exec(Exec),
+    Delete,
+}
+
+#[derive(Debug, Clone)]
+pub struct Exec {
+    command: Vec<String>,
+    in_dir: bool,
+    /// Set for `-exec ... +`: index of the batch collecting this action's
+    /// paths in the `Context`.
+    batch: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Directive {
+    Literal(String),
+    Field(char),
+    Epoch(TimeField),
+}
+
+/// State shared by the actions while walking the search paths.
+#[derive(Debug, Default)]
+pub struct Context {
+    /// Set by `-prune`: do not descend into this directory.
+    pub prune: bool,
+    /// The search path the current entry was found under.
+    pub root: PathBuf,
+    /// Set by `--relative-to`: the absolute directory that printed paths are
+    /// relative to.
+    pub relative_to: Option<PathBuf>,
+    /// Set by `--json`: print entries as JSON objects.
+    pub json: bool,
+    /// Set when the walker sorts the output, which it then takes from `out`
+    /// itself.
+    pub hold_output: bool,
+    /// Output of the printing actions that has not been written yet.
+    pub out: Vec<u8>,
+    batches: Vec<Batch>,
+    failed: bool,
+}
+
+#[derive(Debug, Default)]
+struct Batch {
+    command: Vec<String>,
+    dir: Option<PathBuf>,
+    paths: Vec<OsString>,
+}
+
+impl Context {
+    /// Runs the commands of `-exec ... +` actions that still have paths
+    /// waiting.
+    pub fn finish(&mut self) {
+        self.write_output();
+        for i in 0..self.batches.len() {
+            let result = self.batches[i].flush();
+            self.check_batch(result);
+        }
+    }
+
+    /// Reports an error as `findr: MESSAGE` and remembers that one occurred.
+    pub fn error(&mut self, message: impl Display) {
+        eprintln!("findr: {}", message);
+        self.failed = true;
+    }
+
+    /// Returns true if an error was reported or a batched command failed.
+    pub fn failed(&self) -> bool {
+        self.failed
+    }
+
+    /// Adds the path of `entry` to the output as is, so that names that are
+    /// not valid UTF-8 are printed unchanged, or its JSON object with
+    /// `--json`.
+    pub fn print(&mut self, entry: &Entry, terminator: u8) {
+        let path = self.display_path(entry.path());
+        if self.json {
+            let _ = serde_json::to_writer(&mut self.out, &json_entry(entry, &path));
+        } else {
+            self.out
+                .extend_from_slice(path.as_os_str().as_encoded_bytes());
+        }
+        self.out.push(terminator);
+    }
+
+    /// Returns `path` as it is printed, which differs from the path used to
+    /// access the entry only with `--relative-to`.
+    pub fn display_path<'a>(&self, path: &'a Path) -> Cow<'a, Path> {
+        match (&self.relative_to, std::path::absolute(path)) {
+            (Some(base), Ok(absolute)) => Cow::Owned(relative_path(&absolute, base)),
+            _ => Cow::Borrowed(path),
+        }
+    }
+
+    // As find does, a failing `-exec ... +` command only affects the exit
+    // status.
+    fn check_batch(&mut self, result: Result<bool, String>) {
+        match result {
+            Ok(success) => self.failed |= !success,
+            Err(message) => self.error(message),
+        }
+    }
+
+    /// Writes the buffered output to stdout.
+    pub fn write_output(&mut self) {
+        if !self.out.is_empty() && !self.hold_output {
+            let _ = io::stdout().write_all(&self.out);
+            self.out.clear();
+        }
+    }
+}
+
+impl Batch {
+    fn flush(&mut self) -> Result<bool, String> {
+        if self.paths.is_empty() {
+            return Ok(true);
+        }
+        let (program, args) = self.command.split_first().unwrap();
+        let mut cmd = Command::new(program);
+        cmd.args(&args[..args.len() - 1]).args(&self.paths);
+        if let Some(dir) = &self.dir {
+            cmd.current_dir(dir);
+        }
+        self.paths.clear();
+        spawn(cmd, program)
+    }
+}
+
+impl Exec {
+    /// Builds `-exec`/`-execdir` from the arguments following it, including
+    /// the terminating `;` or `+`.
+    pub fn parse(
+        name: &str,
+        mut args: Vec<String>,
+        next_batch: &mut usize,
+    ) -> Result<Self, String> {
+        let batch = match args.pop().as_deref() {
+            Some(";") => None,
+            Some("+") if args.last().map(String::as_str) == Some("{}") => {
+                *next_batch += 1;
+                Some(*next_batch - 1)
+            }
+            _ => return Err(format!("missing argument to '{}'", name)),
+        };
+        if args.is_empty() || (batch.is_some() && args.len() < 2) {
+            return Err(format!("missing argument to '{}'", name));
+        }
+        Ok(Exec {
+            command: args,
+            in_dir: name == "-execdir",
+            batch,
+        })
+    }
+
+    /// Returns the number of arguments of `-exec`/`-execdir` at the start of
+    /// `args`, including the terminator, or `None` if there is none.
+    pub fn arity(args: &[&str]) -> Option<usize> {
+        args.iter()
+            .enumerate()
+            .position(|(i, a)| *a == ";" || (*a == "+" && i > 0 && args[i - 1] == "{}"))
+            .map(|i| i + 1)
+    }
+
+    fn run(&self, entry: &Entry, ctx: &mut Context) -> bool {
+        // Output of earlier entries must come before the command's.
+        ctx.write_output();
+        let (dir, path) = if self.in_dir {
+            let dir = match entry.path().parent() {
+                Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+                _ => PathBuf::from("."),
+            };
+            (
+                Some(dir),
+                Path::new(".").join(entry.file_name()).into_os_string(),
+            )
+        } else {
+            (None, entry.path().as_os_str().to_os_string())
+        };
+
+        if let Some(i) = self.batch {
+            if ctx.batches.len() <= i {
+                ctx.batches.resize_with(i + 1, Batch::default);
+            }
+            let batch = &mut ctx.batches[i];
+            let result = if batch.dir != dir || batch.paths.len() >= MAX_BATCH {
+                batch.flush()
+            } else {
+                Ok(true)
+            };
+            batch.command.clone_from(&self.command);
+            batch.dir = dir;
+            batch.paths.push(path);
+            ctx.check_batch(result);
+            return true;
+        }
+
+        let (program, args) = self.command.split_first().unwrap();
+        let mut cmd = Command::new(program);
+        cmd.args(args.iter().map(|a| substitute(a, &path)));
+        if let Some(dir) = dir {
+            cmd.current_dir(dir);
+        }
+        spawn(cmd, program).unwrap_or_else(|message| {
+            ctx.error(message);
+            false
+        })
+    }
+}
+
+impl Action {
+    pub fn run(&self, entry: &Entry, ctx: &mut Context) -> bool {
+        match self {
+            Action::Print => {
+                ctx.print(entry, b'\n');
+                true
+            }
+            Action::Print0 => {
+                ctx.print(entry, b'\0');
+                true
+            }
+            Action::Printf(directives) => {
+                let out = format_entry(directives, entry, ctx);
+                ctx.out.extend_from_slice(&out);
+                true
+            }
+            Action::Exec(exec) => exec.run(entry, ctx),
+            Action::Delete => delete(entry, ctx),
+        }
+    }
+}
+
+// Replaces every `{}` in `arg` with `path`.
+fn substitute(arg: &str, path: &OsStr) -> OsString {
+    let mut result = OsString::new();
+    let mut parts = arg.split("{}");
+    if let Some(first) = parts.next() {
+        result.push(first);
+    }
+    for part in parts {
+        result.push(path);
+        result.push(part);
+    }
+    result
+}
+
+// Runs the command, returning whether it succeeded or why it could not be
+// run.
+fn spawn(mut cmd: Command, program: &str) -> Result<bool, String> {
+    // Keep our own output in order with the command's.
+    let _ = io::stdout().flush();
+    cmd.status()
+        .map(|status| status.success())
+        .map_err(|e| format!("{}: {}", program, io_message(&e)))
+}
+
+fn delete(entry: &Entry, ctx: &mut Context) -> bool {
+    let result = if entry.file_type().is_dir() {
+        fs::remove_dir(entry.path())
+    } else {
+        fs::remove_file(entry.path())
+    };
+    match result {
+        Ok(()) => true,
+        Err(e) => {
+            ctx.error(format!(
+                "cannot delete '{}': {}",
+                entry.path().display(),
+                io_message(&e)
+            ));
+            false
+        }
+    }
+}
+
+/// Describes an I/O error without the `(os error N)` suffix of its `Display`
+/// output, e.g. `Permission denied`.
+pub fn io_message(e: &io::Error) -> String {
+    // Errors of the walker wrap the underlying error together with its path.
+    let e = e
+        .get_ref()
+        .and_then(|inner| inner.source())
+        .and_then(|source| source.downcast_ref::<io::Error>())
+        .unwrap_or(e);
+    let message = e.to_string();
+    match message.find(" (os error ") {
+        Some(i) => message[..i].to_string(),
+        None => message,
+    }
+}
+
+/// Parses a `-printf` format. Supported directives are `%p` (path), `%f`
+/// (base name), `%h` (leading directories), `%P` (path below the search
+/// path), `%s` (size), `%a`/`%c`/`%t` (access, change and modification time),
+/// `%A@`/`%C@`/`%T@` (the same as seconds since the epoch), `%m`/`%M`
+/// (octal/symbolic permissions), `%u`/`%g` (owner names), `%U`/`%G` (owner
+/// ids), `%d` (depth), `%y` (type), `%l` (link target) and `%%`, plus the
+/// escapes `\n`, `\t`, `\0` and `\\`.
+pub fn parse_format(format: &str) -> Result<Vec<Directive>, String> {
+    let mut directives = Vec::new();
+    let mut literal = String::new();
+    let mut chars = format.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => literal.push('\n'),
+                Some('t') => literal.push('\t'),
+                Some('0') => literal.push('\0'),
+                Some('\\') => literal.push('\\'),
+                Some(other) => {
+                    literal.push('\\');
+                    literal.push(other);
+                }
+                None => literal.push('\\'),
+            },
+            '%' => {
+                let directive = match chars.next() {
+                    Some('%') => {
+                        literal.push('%');
+                        continue;
+                    }
+                    Some(
+                        c @ ('p' | 'f' | 'h' | 'P' | 's' | 'a' | 'c' | 't' | 'm' | 'M' | 'u' | 'g'
+                        | 'U' | 'G' | 'd' | 'y' | 'l'),
+                    ) => Directive::Field(c),
+                    Some(c @ ('A' | 'C' | 'T')) => match chars.next() {
+                        Some('@') => Directive::Epoch(match c {
+                            'A' => TimeField::Access,
+                            'C' => TimeField::Change,
+                            _ => TimeField::Modify,
+                        }),
+                        _ => return Err(format!("unsupported format directive '%{}'", c)),
+                    },
+                    Some(c) => return Err(format!("unsupported format directive '%{}'", c)),
+                    None => return Err("format ends with a lone '%'".to_string()),
+                };
+                if !literal.is_empty() {
+                    directives.push(Directive::Literal(std::mem::take(&mut literal)));
+                }
+                directives.push(directive);
+            }
+            c => literal.push(c),
+        }
+    }
+    if !literal.is_empty() {
+        directives.push(Directive::Literal(literal));
+    }
+    Ok(directives)
+}
+
+pub fn format_entry(directives: &[Directive], entry: &Entry, ctx: &Context) -> Vec<u8> {
+    let mut out = Vec::new();
+    for directive in directives {
+        match directive {
+            Directive::Literal(s) => out.extend_from_slice(s.as_bytes()),
+            Directive::Field(c) => out.extend_from_slice(&field(*c, entry, ctx)),
+            Directive::Epoch(field) => {
+                if let Some(time) = entry_time(entry, *field) {
+                    let since = time
+                        .duration_since(SystemTime::UNIX_EPOCH)
+                        .unwrap_or_default();
+                    let seconds = format!("{}.{:09}0", since.as_secs(), since.subsec_nanos());
+                    out.extend_from_slice(seconds.as_bytes());
+                }
+            }
+        }
+    }
+    out
+}
+
+// Paths and names are kept as bytes so that they are printed unchanged.
+fn field(c: char, entry: &Entry, ctx: &Context) -> Vec<u8> {
+    let path = entry.path();
+    let bytes = |p: &Path| p.as_os_str().as_encoded_bytes().to_vec();
+    match c {
+        'p' => bytes(&ctx.display_path(path)),
+        'f' => entry.file_name().as_encoded_bytes().to_vec(),
+        'h' => match ctx.display_path(path).parent() {
+            Some(p) if !p.as_os_str().is_empty() => bytes(p),
+            _ => b".".to_vec(),
+        },
+        'P' => path.strip_prefix(&ctx.root).map(bytes).unwrap_or_default(),
+        'l' => fs::read_link(path).map(|p| bytes(&p)).unwrap_or_default(),
+        _ => text_field(c, entry).into_bytes(),
+    }
+}
+
+fn text_field(c: char, entry: &Entry) -> String {
+    let time = |field| {
+        entry_time(entry, field)
+            .map(|t| {
+                DateTime::<Local>::from(t)
+                    .format("%a %b %e %H:%M:%S %Y")
+                    .to_string()
+            })
+            .unwrap_or_default()
+    };
+    match c {
+        's' => entry
+            .metadata()
+            .map(|m| m.len().to_string())
+            .unwrap_or_default(),
+        'a' => time(TimeField::Access),
+        'c' => time(TimeField::Change),
+        't' => time(TimeField::Modify),
+        'm' => entry_mode(entry)
+            .map(|m| format!("{:o}", m))
+            .unwrap_or_default(),
+        'M' => entry_mode(entry)
+            .map(|m| mode_string(type_char(entry), m))
+            .unwrap_or_default(),
+        'u' => entry_owner(entry)
+            .map(|(uid, _)| user_name(uid))
+            .unwrap_or_default(),
+        'g' => entry_owner(entry)
+            .map(|(_, gid)| group_name(gid))
+            .unwrap_or_default(),
+        'U' => entry_owner(entry)
+            .map(|(uid, _)| uid.to_string())
+            .unwrap_or_default(),
+        'G' => entry_owner(entry)
+            .map(|(_, gid)| gid.to_string())
+            .unwrap_or_default(),
+        'd' => entry.depth().to_string(),
+        'y' => type_char(entry).to_string(),
+        _ => String::new(),
+    }
+}
+
+// The object printed by `--json`. Times are in seconds since the epoch and
+// names that are not valid UTF-8 are converted lossily.
+fn json_entry(entry: &Entry, path: &Path) -> serde_json::Value {
+    let meta = entry.metadata().ok();
+    let time = |field| {
+        meta.as_ref()
+            .and_then(|m| meta_time(m, field))
+            .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map(|d| d.as_secs_f64())
+    };
+    serde_json::json!({
+        "path": path.to_string_lossy(),
+        "type": EntryType::of(entry.file_type()).map_or("unknown", EntryType::name),
+        "size": meta.as_ref().map(|m| m.len()),
+        "mode": meta.as_ref().and_then(meta_mode).map(|m| format!("{:o}", m)),
+        "atime": time(TimeField::Access),
+        "mtime": time(TimeField::Modify),
+        "ctime": time(TimeField::Change),
+    })
+}
+
+/// Resolves `.` and `..` in an absolute path without looking at the file
+/// system.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                result.pop();
+            }
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+// Returns the absolute `path` relative to the normalized directory `base`,
+// or `path` itself if they have nothing in common, e.g. on different drives.
+fn relative_path(path: &Path, base: &Path) -> PathBuf {
+    let path = normalize(path);
+    let mut rest = path.components().peekable();
+    let mut up = base.components().peekable();
+    let mut common = false;
+    while let (Some(a), Some(b)) = (rest.peek(), up.peek())
+        && a == b
+    {
+        rest.next();
+        up.next();
+        common = true;
+    }
+    if !common {
+        return path;
+    }
+    let relative: PathBuf = up.map(|_| Component::ParentDir).chain(rest).collect();
+    if relative.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        relative
+    }
+}
+
+fn type_char(entry: &Entry) -> char {
+    EntryType::of(entry.file_type()).map_or('U', EntryType::letter)
+}
+
+/// Renders permissions like `ls -l`, e.g. `-rwxr-xr-x`.
+fn mode_string(type_char: char, mode: u32) -> String {
+    let mut s = String::with_capacity(10);
+    s.push(match type_char {
+        'f' | 'U' => '-',
+        c => c,
+    });
+    for (shift, special, set_char) in [(6, 0o4000, 's'), (3, 0o2000, 's'), (0, 0o1000, 't')] {
+        let bits = (mode >> shift) & 0o7;
+        s.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        s.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        s.push(match (bits & 0o1 != 0, mode & special != 0) {
+            (true, true) => set_char,
+            (false, true) => set_char.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+    s
+}
+
+#[cfg(unix)]
+fn user_name(uid: u32) -> String {
+    uzers::get_user_by_uid(uid)
+        .map(|u| u.name().to_string_lossy().into_owned())
+        .unwrap_or_else(|| uid.to_string())
+}
+
+#[cfg(not(unix))]
+fn user_name(uid: u32) -> String {
+    uid.to_string()
+}
+
+#[cfg(unix)]
+fn group_name(gid: u32) -> String {
+    uzers::get_group_by_gid(gid)
+        .map(|g| g.name().to_string_lossy().into_owned())
+        .unwrap_or_else(|| gid.to_string())
+}
+
+#[cfg(not(unix))]
+fn group_name(gid: u32) -> String {
+    gid.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Directive, Exec, mode_string, parse_format, substitute};
+    use crate::predicate::TimeField;
+    use std::ffi::OsStr;
+
+    #[test]
+    fn test_parse_format() {
+        assert_eq!(
+            parse_format(r"%p\t%s%%\n").unwrap(),
+            vec![
+                Directive::Field('p'),
+                Directive::Literal("\t".to_string()),
+                Directive::Field('s'),
+                Directive::Literal("%\n".to_string()),
+            ]
+        );
+        assert_eq!(
+            parse_format("[%T@]").unwrap(),
+            vec![
+                Directive::Literal("[".to_string()),
+                Directive::Epoch(TimeField::Modify),
+                Directive::Literal("]".to_string()),
+            ]
+        );
+        assert_eq!(
+            parse_format("%q").unwrap_err(),
+            "unsupported format directive '%q'"
+        );
+        assert_eq!(
+            parse_format("%T").unwrap_err(),
+            "unsupported format directive '%T'"
+        );
+        assert_eq!(
+            parse_format("50%").unwrap_err(),
+            "format ends with a lone '%'"
+        );
+    }
+
+    #[test]
+    fn test_exec_arity() {
+        assert_eq!(Exec::arity(&["echo", "{}", ";", "-o"]), Some(3));
+        assert_eq!(Exec::arity(&["echo", "{}", "+"]), Some(3));
+        assert_eq!(Exec::arity(&["echo", "+", "{}", ";"]), Some(4));
+        assert_eq!(Exec::arity(&["echo", "{}"]), None);
+    }
+
+    #[test]
+    fn test_parse_exec() {
+        let mut next_batch = 0;
+        let args = |s: &str| s.split(' ').map(str::to_string).collect::<Vec<_>>();
+
+        let exec = Exec::parse("-exec", args("echo {} ;"), &mut next_batch).unwrap();
+        assert_eq!(exec.batch, None);
+        let exec = Exec::parse("-exec", args("echo {} +"), &mut next_batch).unwrap();
+        assert_eq!(exec.batch, Some(0));
+        let exec = Exec::parse("-execdir", args("ls {} +"), &mut next_batch).unwrap();
+        assert_eq!(exec.batch, Some(1));
+        assert!(exec.in_dir);
+
+        assert!(Exec::parse("-exec", args(";"), &mut next_batch).is_err());
+        assert!(Exec::parse("-exec", args("{} +"), &mut next_batch).is_err());
+        assert!(Exec::parse("-exec", args("echo {}"), &mut next_batch).is_err());
+    }
+
+    #[test]
+    fn test_substitute() {
+        let path = OsStr::new("a/b.txt");
+        assert_eq!(substitute("{}", path), "a/b.txt");
+        assert_eq!(substitute("--file={}.bak", path), "--file=a/b.txt.bak");
+        assert_eq!(substitute("{}:{}", path), "a/b.txt:a/b.txt");
+        assert_eq!(substitute("plain", path), "plain");
+    }
+
+    #[test]
+    fn test_mode_string() {
+        assert_eq!(mode_string('f', 0o644), "-rw-r--r--");
+        assert_eq!(mode_string('d', 0o755), "drwxr-xr-x");
+        assert_eq!(mode_string('f', 0o4755), "-rwsr-xr-x");
+        assert_eq!(mode_string('d', 0o1777), "drwxrwxrwt");
+        assert_eq!(mode_string('f', 0o2644), "-rw-r-Sr--");
+    }
+}
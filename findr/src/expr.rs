@@ -0,0 +1,299 @@
+use std::{ffi::OsString, time::SystemTime};
+
+use crate::{
+    RegexType,
+    action::{self, Action, Context, Exec},
+    predicate::Predicate,
+    walk::Entry,
+};
+
+/// A find-style expression, evaluated once per directory entry.
+#[derive(Debug, Clone)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Predicate(Predicate),
+    Action(Action),
+    Prune,
+}
+
+impl Expr {
+    pub fn and(self, other: Expr) -> Expr {
+        Expr::And(Box::new(self), Box::new(other))
+    }
+
+    pub fn or(self, other: Expr) -> Expr {
+        Expr::Or(Box::new(self), Box::new(other))
+    }
+
+    /// Evaluates the expression left to right with short-circuiting, so
+    /// actions and `-prune` only take effect when they are reached.
+    pub fn is_match(&self, entry: &Entry, ctx: &mut Context) -> bool {
+        match self {
+            Expr::And(lhs, rhs) => lhs.is_match(entry, ctx) && rhs.is_match(entry, ctx),
+            Expr::Or(lhs, rhs) => lhs.is_match(entry, ctx) || rhs.is_match(entry, ctx),
+            Expr::Not(expr) => !expr.is_match(entry, ctx),
+            Expr::Predicate(predicate) => predicate.is_match(entry),
+            Expr::Action(action) => action.run(entry, ctx),
+            Expr::Prune => {
+                ctx.prune = true;
+                true
+            }
+        }
+    }
+
+    /// Returns true if any action in the expression satisfies `f`.
+    pub fn any_action(&self, f: &impl Fn(&Action) -> bool) -> bool {
+        match self {
+            Expr::And(lhs, rhs) | Expr::Or(lhs, rhs) => lhs.any_action(f) || rhs.any_action(f),
+            Expr::Not(expr) => expr.any_action(f),
+            Expr::Action(action) => f(action),
+            Expr::Predicate(_) | Expr::Prune => false,
+        }
+    }
+}
+
+/// Returns the number of arguments in `rest` that belong to `token` if it is
+/// part of the expression, or `None` if it is not.
+pub fn arity(token: &str, rest: &[&str]) -> Option<usize> {
+    match token {
+        "(" | ")" | "!" | "-not" | "-a" | "-and" | "-o" | "-or" | "-prune" => Some(0),
+        "-print" | "-print0" | "-delete" => Some(0),
+        "-printf" => Some(1),
+        // Without a terminator the whole rest is taken so that the parser can
+        // report it.
+        "-exec" | "-execdir" => Some(Exec::arity(rest).unwrap_or(rest.len())),
+        _ => Predicate::arity(token),
+    }
+}
+
+/// Parses the expression tokens, returning `None` for an empty expression.
+///
+/// Operators in decreasing order of precedence are `( )`, `!`/`-not`,
+/// `-a`/`-and` (also implied between two expressions) and `-o`/`-or`.
+pub fn parse(
+    tokens: &[OsString],
+    now: SystemTime,
+    regex_type: RegexType,
+) -> Result<Option<Expr>, String> {
+    let tokens = tokens
+        .iter()
+        .map(|t| {
+            t.to_str()
+                .map(str::to_string)
+                .ok_or_else(|| format!("invalid argument {:?}", t))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if tokens.is_empty() {
+        return Ok(None);
+    }
+
+    let mut parser = ExprParser {
+        tokens: &tokens,
+        pos: 0,
+        now,
+        regex_type,
+        batches: 0,
+    };
+    let expr = parser.parse_or()?;
+    match parser.peek() {
+        None => Ok(Some(expr)),
+        Some(token) => Err(format!("unexpected '{}' in expression", token)),
+    }
+}
+
+struct ExprParser<'a> {
+    tokens: &'a [String],
+    pos: usize,
+    now: SystemTime,
+    regex_type: RegexType,
+    batches: usize,
+}
+
+impl ExprParser<'_> {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Option<&str> {
+        let token = self.tokens.get(self.pos).map(String::as_str);
+        self.pos += 1;
+        token
+    }
+
+    fn previous(&self) -> &str {
+        &self.tokens[self.pos - 1]
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_and()?;
+        while let Some("-o" | "-or") = self.peek() {
+            self.next();
+            expr = expr.or(self.parse_and()?);
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_unary()?;
+        loop {
+            match self.peek() {
+                Some("-a" | "-and") => {
+                    self.next();
+                }
+                Some(_) if !self.at_operator() => {}
+                _ => break,
+            }
+            expr = expr.and(self.parse_unary()?);
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        match self.peek() {
+            None if self.pos == 0 => Err("empty expression".to_string()),
+            None => Err(format!(
+                "expected an expression after '{}'",
+                self.previous()
+            )),
+            Some("!" | "-not") => {
+                self.next();
+                Ok(Expr::Not(Box::new(self.parse_unary()?)))
+            }
+            Some("(") => {
+                self.next();
+                if self.peek() == Some(")") {
+                    return Err("empty parentheses are not allowed".to_string());
+                }
+                let expr = self.parse_or()?;
+                match self.next() {
+                    Some(")") => Ok(expr),
+                    _ => Err("missing ')' in expression".to_string()),
+                }
+            }
+            Some(token @ (")" | "-a" | "-and" | "-o" | "-or")) => {
+                Err(format!("expected an expression before '{}'", token))
+            }
+            Some("-prune") => {
+                self.next();
+                Ok(Expr::Prune)
+            }
+            Some("-print") => {
+                self.next();
+                Ok(Expr::Action(Action::Print))
+            }
+            Some("-print0") => {
+                self.next();
+                Ok(Expr::Action(Action::Print0))
+            }
+            Some("-delete") => {
+                self.next();
+                Ok(Expr::Action(Action::Delete))
+            }
+            Some("-printf") => {
+                self.next();
+                let format = self
+                    .next()
+                    .ok_or_else(|| "missing argument to '-printf'".to_string())?;
+                let directives = action::parse_format(format)
+                    .map_err(|e| format!("invalid argument to '-printf': {}", e))?;
+                Ok(Expr::Action(Action::Printf(directives)))
+            }
+            Some(name @ ("-exec" | "-execdir")) => {
+                let name = name.to_string();
+                self.next();
+                let rest: Vec<&str> = self.tokens[self.pos..].iter().map(String::as_str).collect();
+                let n = Exec::arity(&rest).unwrap_or(rest.len());
+                let args = rest[..n].iter().map(|a| a.to_string()).collect();
+                self.pos += n;
+                let exec = Exec::parse(&name, args, &mut self.batches)?;
+                Ok(Expr::Action(Action::Exec(exec)))
+            }
+            Some(token) if !token.starts_with('-') => {
+                Err(format!("paths must precede expression: '{}'", token))
+            }
+            Some(_) => {
+                let name = self.next().unwrap_or_default().to_string();
+                let arg = match Predicate::arity(&name) {
+                    Some(0) | None => None,
+                    Some(_) => self.next().map(str::to_string),
+                };
+                Predicate::parse(&name, arg.as_deref(), self.now, self.regex_type)
+                    .map(Expr::Predicate)
+            }
+        }
+    }
+
+    // Tokens that end an implicit `-a` chain.
+    fn at_operator(&self) -> bool {
+        matches!(self.peek(), Some(")" | "-o" | "-or"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Expr, parse};
+    use crate::RegexType;
+    use std::{ffi::OsString, time::SystemTime};
+
+    fn parse_str(s: &str) -> Result<Option<Expr>, String> {
+        let tokens: Vec<OsString> = s.split_whitespace().map(OsString::from).collect();
+        parse(&tokens, SystemTime::now(), RegexType::Rust)
+    }
+
+    // Renders the tree with explicit grouping so precedence is visible.
+    fn show(expr: &Expr) -> String {
+        match expr {
+            Expr::And(l, r) => format!("({} & {})", show(l), show(r)),
+            Expr::Or(l, r) => format!("({} | {})", show(l), show(r)),
+            Expr::Not(e) => format!("!{}", show(e)),
+            Expr::Predicate(_) => "p".to_string(),
+            Expr::Action(_) => "action".to_string(),
+            Expr::Prune => "prune".to_string(),
+        }
+    }
+
+    fn shape(s: &str) -> String {
+        show(&parse_str(s).unwrap().unwrap())
+    }
+
+    #[test]
+    fn test_precedence() {
+        assert_eq!(shape("-empty"), "p");
+        assert_eq!(shape("-empty -empty"), "(p & p)");
+        assert_eq!(shape("-empty -a -empty -o -empty"), "((p & p) | p)");
+        assert_eq!(shape("-empty -o -empty -empty"), "(p | (p & p))");
+        assert_eq!(shape("! -empty -o -empty"), "(!p | p)");
+        assert_eq!(shape("-not ( -empty -o -empty )"), "!(p | p)");
+        assert_eq!(
+            shape("( -empty -or -empty ) -and -size +1k"),
+            "((p | p) & p)"
+        );
+        assert_eq!(shape("-empty -prune -o -empty"), "((p & prune) | p)");
+        assert_eq!(
+            shape("-empty -exec rm {} ; -o -print"),
+            "((p & action) | action)"
+        );
+        assert_eq!(shape("-exec echo {} + -print0"), "(action & action)");
+        assert!(parse_str("").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = |s| parse_str(s).unwrap_err();
+        assert_eq!(err("-o -empty"), "expected an expression before '-o'");
+        assert_eq!(err("-empty -o"), "expected an expression after '-o'");
+        assert_eq!(err("!"), "expected an expression after '!'");
+        assert_eq!(err("( -empty"), "missing ')' in expression");
+        assert_eq!(err("-empty )"), "unexpected ')' in expression");
+        assert_eq!(err("( )"), "empty parentheses are not allowed");
+        assert_eq!(err("-empty -a -a"), "expected an expression before '-a'");
+        assert_eq!(err("-size"), "missing argument to '-size'");
+        assert_eq!(err("-exec echo {}"), "missing argument to '-exec'");
+        assert_eq!(
+            err("-printf %q"),
+            "invalid argument to '-printf': unsupported format directive '%q'"
+        );
+    }
+}
@@ -1,13 +1,85 @@
-use clap::Parser;
+mod action;
+mod expr;
+mod predicate;
+mod walk;
+
+use action::{Action, Context};
 use clap::ValueEnum;
-use regex::Regex;
+use clap::{CommandFactory, Parser};
+use expr::Expr;
+use globset::GlobMatcher;
+use predicate::Predicate;
 use std::error::Error;
-use walkdir::DirEntry;
-use walkdir::WalkDir;
+use std::ffi::OsString;
+use std::fs::FileType;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::SystemTime;
+use walk::Entry;
 
 type MyResult<T> = Result<T, Box<dyn Error>>;
 
-#[derive(Debug, PartialEq, Eq, ValueEnum, Clone)]
+const EXPRESSION_HELP: &str = "\
+Expression:
+  EXPR1 [-a|-and] EXPR2  Both expressions are true (the default between two expressions)
+  EXPR1 -o|-or EXPR2     Either expression is true
+  ! EXPR, -not EXPR      Expression is false
+  ( EXPR )               Group expressions; binds tighter than !, -a and -o
+
+  -maxdepth N, -mindepth N, -depth, -xdev, -mount, -follow and -regextype TYPE
+  are accepted as aliases of the corresponding options.
+
+  -prune                 Do not descend into the directory; always true
+
+Tests:
+  -name GLOB             Base name matches the shell pattern GLOB
+  -iname GLOB            Like -name, but case-insensitive
+  -path GLOB             Whole path matches GLOB; * and ? also match /
+  -ipath GLOB            Like -path, but case-insensitive
+  -regex REGEX           Whole path matches REGEX (see -regextype)
+  -iregex REGEX          Like -regex, but case-insensitive
+  -type f|d|l|s|p|b|c[,...]
+                         Entry is a file, directory, symbolic link, socket, FIFO,
+                         block or character device
+  -xtype TYPE[,...]      Like -type, but for symbolic links checks the other side
+                         of the link: the target, or the link itself with -L
+  -size [+-]N[cwbkMG]    File uses N units of space (default: 512-byte blocks)
+  -atime/-mtime/-ctime [+-]N
+                         Accessed/modified/changed N days ago
+  -amin/-mmin/-cmin [+-]N
+                         Accessed/modified/changed N minutes ago
+  -newer FILE            Modified more recently than FILE
+  -perm [-/]MODE         Permission bits are exactly (all of -, any of /) octal MODE
+  -user NAME             Owned by user NAME or numeric id
+  -group NAME            Owned by group NAME or numeric id
+  -empty                 Empty file or directory
+  -executable, -readable, -writable
+                         The current user can execute (or search), read or write
+                         the entry
+  -contains REGEX        Regular file with a line matching REGEX
+
+  +N means more than N, -N means less than N, N means exactly N.
+
+Actions (without any, -print is applied to entries matching the expression):
+  -print                 Print the path followed by a newline
+  -print0                Print the path followed by a NUL character
+  -printf FORMAT         Print FORMAT with directives such as %p (path), %f (name),
+                         %s (size), %t (modification time) and %m (permissions)
+  -exec CMD {} ;         Run CMD with {} replaced by the path; true if it succeeds
+  -exec CMD {} +         Run CMD with as many paths as possible at once
+  -execdir CMD {} ;|+    Like -exec, but run from the entry's directory
+  -delete                Delete the entry; implies -depth
+
+  With more than one thread, output appears in the order entries are found
+  unless --sort is given. -depth, -delete, -exec and -execdir always walk
+  with a single thread.
+
+  --sort holds back the output of each search path until it has been walked;
+  commands still run in the order entries are found. --relative-to changes
+  the paths printed by -print, -print0 and %p, but not those passed to
+  commands. With --json, -print and -print0 print JSON objects.";
+
+#[derive(Debug, PartialEq, Eq, ValueEnum, Clone, Copy)]
 enum EntryType {
     #[value(name = "f")]
     File,
@@ -15,20 +87,108 @@ enum EntryType {
     Dir,
     #[value(name = "l")]
     Link,
+    #[value(name = "s")]
+    Socket,
+    #[value(name = "p")]
+    Fifo,
+    #[value(name = "b")]
+    BlockDevice,
+    #[value(name = "c")]
+    CharDevice,
+}
+
+impl EntryType {
+    /// Returns the type of a file, or `None` if it is none of the above.
+    fn of(file_type: FileType) -> Option<EntryType> {
+        if file_type.is_dir() {
+            Some(EntryType::Dir)
+        } else if file_type.is_file() {
+            Some(EntryType::File)
+        } else if file_type.is_symlink() {
+            Some(EntryType::Link)
+        } else {
+            EntryType::special(file_type)
+        }
+    }
+
+    #[cfg(unix)]
+    fn special(file_type: FileType) -> Option<EntryType> {
+        use std::os::unix::fs::FileTypeExt;
+
+        if file_type.is_socket() {
+            Some(EntryType::Socket)
+        } else if file_type.is_fifo() {
+            Some(EntryType::Fifo)
+        } else if file_type.is_block_device() {
+            Some(EntryType::BlockDevice)
+        } else if file_type.is_char_device() {
+            Some(EntryType::CharDevice)
+        } else {
+            None
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn special(_file_type: FileType) -> Option<EntryType> {
+        None
+    }
+
+    /// The letter used by `-type` and `%y`.
+    fn letter(self) -> char {
+        match self {
+            EntryType::File => 'f',
+            EntryType::Dir => 'd',
+            EntryType::Link => 'l',
+            EntryType::Socket => 's',
+            EntryType::Fifo => 'p',
+            EntryType::BlockDevice => 'b',
+            EntryType::CharDevice => 'c',
+        }
+    }
+
+    /// The name used by `--json`.
+    fn name(self) -> &'static str {
+        match self {
+            EntryType::File => "file",
+            EntryType::Dir => "directory",
+            EntryType::Link => "symlink",
+            EntryType::Socket => "socket",
+            EntryType::Fifo => "fifo",
+            EntryType::BlockDevice => "block_device",
+            EntryType::CharDevice => "char_device",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, ValueEnum, Clone, Copy)]
+enum SortKey {
+    Name,
+    Size,
+    Mtime,
+}
+
+#[derive(Debug, PartialEq, Eq, ValueEnum, Clone, Copy)]
+enum RegexType {
+    #[value(name = "rust", aliases = ["posix-extended", "egrep"])]
+    Rust,
+    #[value(name = "pcre")]
+    Pcre,
 }
 
 #[derive(Debug, Parser)]
+#[command(after_help = EXPRESSION_HELP)]
 pub struct Config {
     #[arg(default_value = ".", help = "Search paths")]
-    paths: Vec<String>,
+    paths: Vec<PathBuf>,
     #[arg(
         value_name="NAME",
         short='n',
         long="name",
-        help = "Name",
+        help = "Name (shell pattern)",
         num_args(0..),
+        value_parser = parse_name,
     )]
-    names: Vec<Regex>,
+    names: Vec<GlobMatcher>,
     #[arg(
         value_name="TYPE",
         short='t',
@@ -38,59 +198,263 @@ pub struct Config {
         value_enum,
     )]
     entry_types: Vec<EntryType>,
+    #[arg(
+        long,
+        value_name = "LEVELS",
+        help = "Descend at most LEVELS below the search paths"
+    )]
+    maxdepth: Option<usize>,
+    #[arg(
+        long,
+        value_name = "LEVELS",
+        help = "Ignore entries less than LEVELS below the search paths"
+    )]
+    mindepth: Option<usize>,
+    #[arg(long, help = "Process directory contents before the directory itself")]
+    depth: bool,
+    #[arg(long, help = "Do not descend into other file systems")]
+    xdev: bool,
+    #[arg(short = 'L', long, help = "Follow symbolic links")]
+    follow: bool,
+    #[arg(
+        long,
+        value_name = "TYPE",
+        value_enum,
+        default_value_t = RegexType::Rust,
+        help = "Regular expression syntax for -regex and -iregex"
+    )]
+    regextype: RegexType,
+    #[arg(
+        short = 'j',
+        long,
+        value_name = "N",
+        default_value_t = 1,
+        help = "Walk with N threads (0 for one per CPU)"
+    )]
+    threads: usize,
+    #[arg(
+        long,
+        value_name = "KEY",
+        value_enum,
+        help = "Sort the output of each search path by KEY"
+    )]
+    sort: Option<SortKey>,
+    #[arg(long, requires = "sort", help = "Reverse the order of --sort")]
+    reverse: bool,
+    #[arg(long, help = "Make the search paths absolute")]
+    absolute: bool,
+    #[arg(
+        long,
+        value_name = "DIR",
+        conflicts_with = "absolute",
+        help = "Print paths relative to DIR"
+    )]
+    relative_to: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Print a JSON object with the path, type, size, mode and times of each entry"
+    )]
+    json: bool,
+    #[arg(
+        long,
+        help = "Skip entries matched by .gitignore, .ignore and global git excludes, and hidden entries"
+    )]
+    respect_ignore: bool,
+    #[arg(long, help = "Do not skip hidden entries with --respect-ignore")]
+    hidden: bool,
+    #[arg(skip)]
+    expression: Option<Expr>,
 }
 
 pub fn get_args() -> MyResult<Config> {
-    let config = Config::parse();
+    let (args, expression) = split_args(std::env::args_os());
+    let mut config = match Config::try_parse_from(args) {
+        Ok(config) => config,
+        // --help
+        Err(e) if !e.use_stderr() => e.exit(),
+        Err(e) => return Err(usage_error(&e)),
+    };
+
+    // --name and --type are shorthands that are combined with the expression
+    // by an implicit -and.
+    let mut filters = Vec::new();
+    if !config.names.is_empty() {
+        filters.push(Expr::Predicate(Predicate::Name(config.names.clone())));
+    }
+    if !config.entry_types.is_empty() {
+        filters.push(Expr::Predicate(Predicate::Type(config.entry_types.clone())));
+    }
+    filters.extend(expr::parse(
+        &expression,
+        SystemTime::now(),
+        config.regextype,
+    )?);
+    config.expression = filters.into_iter().reduce(Expr::and);
+
+    if config.absolute {
+        for path in &mut config.paths {
+            *path = action::normalize(&absolute(path)?);
+        }
+    }
+    if let Some(dir) = &config.relative_to {
+        config.relative_to = Some(action::normalize(&absolute(dir)?));
+    }
 
     Ok(config)
 }
 
-fn is_match_entry_type(entry: &DirEntry, entry_types: &[EntryType]) -> bool {
-    if entry_types.is_empty() {
-        true
-    } else if entry.file_type().is_dir() {
-        entry_types.contains(&EntryType::Dir)
-    } else if entry.file_type().is_file() {
-        entry_types.contains(&EntryType::File)
-    } else if entry.file_type().is_symlink() {
-        entry_types.contains(&EntryType::Link)
-    } else {
-        false
+// Separates find-style expression tokens such as `-size +1k` or `(` from the
+// arguments handled by clap, which does not understand single-dash long
+// options. Find's global options are rewritten to their clap spelling. Other
+// words of a dash and several letters, such as a misspelled predicate, start
+// the expression too. Once it has started, only find's global options and
+// clap's options with their values are taken out of it, so that the parser
+// reports unknown predicates and misplaced paths.
+fn split_args(args: impl IntoIterator<Item = OsString>) -> (Vec<OsString>, Vec<OsString>) {
+    let args: Vec<OsString> = args.into_iter().collect();
+    let lossy: Vec<_> = args.iter().map(|a| a.to_string_lossy()).collect();
+    let tokens: Vec<&str> = lossy.iter().map(|t| t.as_ref()).collect();
+    let mut command = Config::command();
+    command.build();
+    let mut clap_args = Vec::new();
+    let mut expression = Vec::new();
+    let mut i = 0;
+
+    while i < args.len() {
+        if let Some((option, n)) = find_option(tokens[i]) {
+            let end = (i + 1 + n).min(args.len());
+            clap_args.push(option.into());
+            clap_args.extend_from_slice(&args[i + 1..end]);
+            i = end;
+        } else if let Some(n) = expr::arity(tokens[i], &tokens[i + 1..]) {
+            let end = (i + 1 + n).min(args.len());
+            expression.extend_from_slice(&args[i..end]);
+            i = end;
+        } else if expression.is_empty() && !is_find_style(tokens[i]) {
+            clap_args.push(args[i].clone());
+            i += 1;
+        } else if let Some(n) = clap_option(&command, tokens[i], &tokens[i + 1..]) {
+            clap_args.extend_from_slice(&args[i..=i + n]);
+            i += 1 + n;
+        } else {
+            expression.push(args[i].clone());
+            i += 1;
+        }
+    }
+    (clap_args, expression)
+}
+
+// Tells `-nam` from clap's short options such as `-L` or `-j`.
+fn is_find_style(token: &str) -> bool {
+    token.len() > 2 && token.starts_with('-') && !token.starts_with("--")
+}
+
+// Returns how many of the words in `rest` are values of `token`, if it is
+// one of clap's options.
+fn clap_option(command: &clap::Command, token: &str, rest: &[&str]) -> Option<usize> {
+    let arg = match token.strip_prefix("--") {
+        Some(long) => {
+            let (long, value) = long.split_once('=').unwrap_or((long, ""));
+            let arg = command
+                .get_arguments()
+                .find(|a| a.get_long() == Some(long))?;
+            if !value.is_empty() {
+                return Some(0);
+            }
+            arg
+        }
+        None => {
+            let mut chars = token.strip_prefix('-')?.chars();
+            let (Some(short), None) = (chars.next(), chars.next()) else {
+                return None;
+            };
+            command
+                .get_arguments()
+                .find(|a| a.get_short() == Some(short))?
+        }
+    };
+    if !arg.get_action().takes_values() {
+        return Some(0);
+    }
+    let max = arg.get_num_args().map_or(1, |n| n.max_values());
+    Some(
+        rest.iter()
+            .take(max)
+            .take_while(|w| !w.starts_with('-'))
+            .count(),
+    )
+}
+
+// Returns the clap spelling of a global option of find, with the number of
+// arguments it takes.
+fn find_option(token: &str) -> Option<(&'static str, usize)> {
+    match token {
+        "-maxdepth" => Some(("--maxdepth", 1)),
+        "-mindepth" => Some(("--mindepth", 1)),
+        "-depth" => Some(("--depth", 0)),
+        "-xdev" | "-mount" => Some(("--xdev", 0)),
+        "-follow" => Some(("--follow", 0)),
+        "-regextype" => Some(("--regextype", 1)),
+        _ => None,
     }
 }
 
-fn is_match_name(entry: &DirEntry, names: &[Regex]) -> bool {
+// Words clap's errors like the others, as `findr: invalid value ...`
+// followed by clap's hints.
+fn usage_error(e: &clap::Error) -> Box<dyn Error> {
+    let message = e.render().to_string();
+    let message = message.strip_prefix("error: ").unwrap_or(&message);
+    message.trim_end().into()
+}
+
+fn absolute(path: &Path) -> MyResult<PathBuf> {
+    std::path::absolute(path).map_err(|e| format!("{}: {}", path.display(), e).into())
+}
+
+fn parse_name(s: &str) -> Result<GlobMatcher, String> {
+    predicate::glob(s, false, true)
+}
+
+fn is_match_entry_type(entry: &Entry, entry_types: &[EntryType]) -> bool {
+    entry_types.is_empty()
+        || EntryType::of(entry.file_type()).is_some_and(|t| entry_types.contains(&t))
+}
+
+fn is_match_name(entry: &Entry, names: &[GlobMatcher]) -> bool {
     if names.is_empty() {
         return true;
     }
-    match entry.file_name().to_str() {
-        Some(fname) => {
-            for regex in names {
-                if regex.is_match(fname) {
-                    return true;
-                }
-            }
-            false
-        }
-        None => false,
+    names.iter().any(|glob| glob.is_match(entry.file_name()))
+}
+
+/// Returns `Ok(false)` if an error was reported while walking, in which case
+/// the exit status should be 1.
+pub fn run(config: Config) -> MyResult<bool> {
+    let expression = config.expression.as_ref();
+    let print = expression.is_none_or(|expr| !expr.any_action(&|_| true));
+    let contents_first = config.depth
+        || expression.is_some_and(|expr| expr.any_action(&|a| matches!(a, Action::Delete)));
+
+    // Running commands and deleting depend on the order of the walk.
+    let sequential = contents_first
+        || expression.is_some_and(|expr| {
+            expr.any_action(&|a| matches!(a, Action::Exec(_) | Action::Delete))
+        });
+    let threads = match config.threads {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    };
+    if threads > 1 && !sequential {
+        Ok(walk::parallel(&config, expression, print, threads))
+    } else {
+        Ok(walk::sequential(&config, expression, print, contents_first))
     }
 }
 
-pub fn run(config: Config) -> MyResult<()> {
-    for path in config.paths {
-        for entry in WalkDir::new(path) {
-            match entry {
-                Err(e) => eprintln!("{}", e),
-                Ok(entry) => {
-                    if is_match_entry_type(&entry, &config.entry_types)
-                        && is_match_name(&entry, &config.names)
-                    {
-                        println!("{}", entry.path().display())
-                    }
-                }
-            }
-        }
+// Evaluates the expression for one entry, leaving any output in `ctx`.
+fn visit(entry: &Entry, expression: Option<&Expr>, print: bool, ctx: &mut Context) {
+    ctx.prune = false;
+    if expression.is_none_or(|expr| expr.is_match(entry, ctx)) && print {
+        ctx.print(entry, b'\n');
     }
-    Ok(())
 }
@@ -0,0 +1,753 @@
+use std::{
+    borrow::Cow,
+    fs::{self, File},
+    io::{BufRead, BufReader},
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+
+use clap::ValueEnum;
+use globset::{GlobBuilder, GlobMatcher};
+use regex::bytes::RegexBuilder;
+
+use crate::{EntryType, RegexType, is_match_entry_type, is_match_name, walk::Entry};
+
+const DAY: u64 = 24 * 60 * 60;
+const MINUTE: u64 = 60;
+
+/// A numeric argument as written by find: `+N` is more than N, `-N` is less
+/// than N and a bare `N` is exactly N.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cmp {
+    Less(u64),
+    Exactly(u64),
+    Greater(u64),
+}
+
+impl Cmp {
+    fn parse(s: &str) -> Option<(Self, &str)> {
+        let (ctor, rest): (fn(u64) -> Cmp, &str) = match s.as_bytes().first() {
+            Some(b'+') => (Cmp::Greater, &s[1..]),
+            Some(b'-') => (Cmp::Less, &s[1..]),
+            _ => (Cmp::Exactly, s),
+        };
+        let end = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let n = rest[..end].parse().ok()?;
+        Some((ctor(n), &rest[end..]))
+    }
+
+    fn matches(&self, value: u64) -> bool {
+        match *self {
+            Cmp::Less(n) => value < n,
+            Cmp::Exactly(n) => value == n,
+            Cmp::Greater(n) => value > n,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeField {
+    Access,
+    Modify,
+    Change,
+}
+
+/// Permission test for `-perm`: `MODE` requires exactly these bits, `-MODE`
+/// requires all of them and `/MODE` requires any of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Perm {
+    Exact(u32),
+    All(u32),
+    Any(u32),
+}
+
+/// The access checked by `-readable`, `-writable` and `-executable`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+/// A compiled `-regex`, anchored to match the whole path.
+#[derive(Debug, Clone)]
+pub enum PathRegex {
+    Rust(regex::bytes::Regex),
+    Pcre(fancy_regex::Regex),
+}
+
+impl PathRegex {
+    pub fn new(
+        pattern: &str,
+        regex_type: RegexType,
+        case_insensitive: bool,
+    ) -> Result<Self, String> {
+        let build = |pattern: &str| match regex_type {
+            RegexType::Rust => RegexBuilder::new(pattern)
+                .case_insensitive(case_insensitive)
+                .build()
+                .map(PathRegex::Rust)
+                .map_err(|e| e.to_string()),
+            RegexType::Pcre => {
+                let flags = if case_insensitive { "(?i)" } else { "" };
+                fancy_regex::Regex::new(&format!("{}{}", flags, pattern))
+                    .map(PathRegex::Pcre)
+                    .map_err(|e| e.to_string())
+            }
+        };
+        // Compile the pattern on its own first, so that errors show it as
+        // it was given.
+        build(pattern)?;
+        build(&format!("^(?:{})$", pattern))
+    }
+
+    /// Rust regexes match the bytes of the path, so `(?-u:\xFF)` matches a
+    /// byte that is not valid UTF-8. As `.` only matches valid UTF-8, such
+    /// paths are also tried with the invalid bytes replaced by U+FFFD, which
+    /// is all that PCRE regexes see.
+    pub fn is_match(&self, path: &Path) -> bool {
+        let lossy = path.to_string_lossy();
+        match self {
+            PathRegex::Rust(re) => {
+                re.is_match(path.as_os_str().as_encoded_bytes())
+                    || (matches!(lossy, Cow::Owned(_)) && re.is_match(lossy.as_bytes()))
+            }
+            PathRegex::Pcre(re) => re.is_match(&lossy).unwrap_or(false),
+        }
+    }
+}
+
+/// Compiles a shell glob. `*` and `?` do not match `/` in `-name` patterns,
+/// but do in `-path` patterns, as with find.
+pub fn glob(
+    pattern: &str,
+    case_insensitive: bool,
+    literal_separator: bool,
+) -> Result<GlobMatcher, String> {
+    GlobBuilder::new(pattern)
+        .case_insensitive(case_insensitive)
+        .literal_separator(literal_separator)
+        .backslash_escape(true)
+        .build()
+        .map(|g| g.compile_matcher())
+        .map_err(|e| e.to_string())
+}
+
+#[derive(Debug, Clone)]
+pub enum Predicate {
+    Name(Vec<GlobMatcher>),
+    Path(GlobMatcher),
+    Regex(PathRegex),
+    Type(Vec<EntryType>),
+    XType(Vec<EntryType>),
+    Size {
+        cmp: Cmp,
+        unit: u64,
+    },
+    Time {
+        field: TimeField,
+        cmp: Cmp,
+        unit: u64,
+        now: SystemTime,
+    },
+    Newer(SystemTime),
+    Perm(Perm),
+    User(u32),
+    Group(u32),
+    Empty,
+    Access(Access),
+    Contains(regex::bytes::Regex),
+}
+
+impl Predicate {
+    /// Builds the predicate for the primary `name` (e.g. `-size`) with its
+    /// argument, if it takes one.
+    pub fn parse(
+        name: &str,
+        arg: Option<&str>,
+        now: SystemTime,
+        regex_type: RegexType,
+    ) -> Result<Self, String> {
+        let arg = || arg.ok_or_else(|| format!("missing argument to '{}'", name));
+        let invalid = |a: &str| format!("invalid argument '{}' to '{}'", a, name);
+        // Patterns keep the reason the compiler gives.
+        let invalid_pattern = |a: &str, e: String| format!("{}: {}", invalid(a), e);
+
+        let time = |field, unit| -> Result<Predicate, String> {
+            let a = arg()?;
+            match Cmp::parse(a) {
+                Some((cmp, "")) => Ok(Predicate::Time {
+                    field,
+                    cmp,
+                    unit,
+                    now,
+                }),
+                _ => Err(invalid(a)),
+            }
+        };
+
+        match name {
+            "-name" | "-iname" => {
+                let a = arg()?;
+                let glob = glob(a, name == "-iname", true).map_err(|e| invalid_pattern(a, e))?;
+                Ok(Predicate::Name(vec![glob]))
+            }
+            "-path" | "-ipath" => {
+                let a = arg()?;
+                let glob = glob(a, name == "-ipath", false).map_err(|e| invalid_pattern(a, e))?;
+                Ok(Predicate::Path(glob))
+            }
+            "-regex" | "-iregex" => {
+                let a = arg()?;
+                let regex = PathRegex::new(a, regex_type, name == "-iregex")
+                    .map_err(|e| invalid_pattern(a, e))?;
+                Ok(Predicate::Regex(regex))
+            }
+            "-type" | "-xtype" => {
+                let a = arg()?;
+                let entry_types = a
+                    .split(',')
+                    .map(|t| EntryType::from_str(t, false))
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| invalid(a))?;
+                if name == "-type" {
+                    Ok(Predicate::Type(entry_types))
+                } else {
+                    Ok(Predicate::XType(entry_types))
+                }
+            }
+            "-size" => {
+                let a = arg()?;
+                let (cmp, suffix) = Cmp::parse(a).ok_or_else(|| invalid(a))?;
+                let unit = match suffix {
+                    "c" => 1,
+                    "w" => 2,
+                    "" | "b" => 512,
+                    "k" => 1024,
+                    "M" => 1024 * 1024,
+                    "G" => 1024 * 1024 * 1024,
+                    _ => return Err(invalid(a)),
+                };
+                Ok(Predicate::Size { cmp, unit })
+            }
+            "-atime" => time(TimeField::Access, DAY),
+            "-mtime" => time(TimeField::Modify, DAY),
+            "-ctime" => time(TimeField::Change, DAY),
+            "-amin" => time(TimeField::Access, MINUTE),
+            "-mmin" => time(TimeField::Modify, MINUTE),
+            "-cmin" => time(TimeField::Change, MINUTE),
+            "-newer" => {
+                let a = arg()?;
+                let modified = fs::metadata(a)
+                    .and_then(|m| m.modified())
+                    .map_err(|e| format!("{}: {}", a, e))?;
+                Ok(Predicate::Newer(modified))
+            }
+            "-perm" => {
+                let a = arg()?;
+                let (ctor, mode): (fn(u32) -> Perm, &str) = match a.as_bytes().first() {
+                    Some(b'-') => (Perm::All, &a[1..]),
+                    Some(b'/') => (Perm::Any, &a[1..]),
+                    _ => (Perm::Exact, a),
+                };
+                let mode = u32::from_str_radix(mode, 8)
+                    .ok()
+                    .filter(|m| *m <= 0o7777)
+                    .ok_or_else(|| invalid(a))?;
+                Ok(Predicate::Perm(ctor(mode)))
+            }
+            "-user" => {
+                let a = arg()?;
+                lookup_user(a)
+                    .map(Predicate::User)
+                    .ok_or_else(|| invalid(a))
+            }
+            "-group" => {
+                let a = arg()?;
+                lookup_group(a)
+                    .map(Predicate::Group)
+                    .ok_or_else(|| invalid(a))
+            }
+            "-empty" => Ok(Predicate::Empty),
+            "-readable" => Ok(Predicate::Access(Access::Read)),
+            "-writable" => Ok(Predicate::Access(Access::Write)),
+            "-executable" => Ok(Predicate::Access(Access::Execute)),
+            "-contains" => {
+                let a = arg()?;
+                RegexBuilder::new(a)
+                    .build()
+                    .map(Predicate::Contains)
+                    .map_err(|e| invalid_pattern(a, e.to_string()))
+            }
+            _ => Err(format!("unknown predicate '{}'", name)),
+        }
+    }
+
+    /// Returns the number of arguments the primary `name` takes, or `None`
+    /// if it is not a predicate.
+    pub fn arity(name: &str) -> Option<usize> {
+        match name {
+            "-name" | "-iname" | "-path" | "-ipath" | "-regex" | "-iregex" | "-type" | "-xtype"
+            | "-size" | "-atime" | "-mtime" | "-ctime" | "-amin" | "-mmin" | "-cmin" | "-newer"
+            | "-perm" | "-user" | "-group" | "-contains" => Some(1),
+            "-empty" | "-readable" | "-writable" | "-executable" => Some(0),
+            _ => None,
+        }
+    }
+
+    pub fn is_match(&self, entry: &Entry) -> bool {
+        match self {
+            Predicate::Name(names) => is_match_name(entry, names),
+            Predicate::Path(glob) => glob.is_match(entry.path()),
+            Predicate::Regex(regex) => regex.is_match(entry.path()),
+            Predicate::Type(entry_types) => is_match_entry_type(entry, entry_types),
+            Predicate::XType(entry_types) => is_match_xtype(entry, entry_types),
+            Predicate::Size { cmp, unit } => is_match_size(entry, *cmp, *unit),
+            Predicate::Time {
+                field,
+                cmp,
+                unit,
+                now,
+            } => is_match_time(entry, *field, *cmp, *unit, *now),
+            Predicate::Newer(time) => is_match_newer(entry, *time),
+            Predicate::Perm(perm) => is_match_perm(entry, *perm),
+            Predicate::User(uid) => is_match_user(entry, *uid),
+            Predicate::Group(gid) => is_match_group(entry, *gid),
+            Predicate::Empty => is_match_empty(entry),
+            Predicate::Access(access) => is_match_access(entry, *access),
+            Predicate::Contains(regex) => is_match_contains(entry, regex),
+        }
+    }
+}
+
+/// The size is rounded up to whole units before comparing, as find does.
+pub fn is_match_size(entry: &Entry, cmp: Cmp, unit: u64) -> bool {
+    match entry.metadata() {
+        Ok(meta) => cmp.matches(meta.len().div_ceil(unit)),
+        Err(_) => false,
+    }
+}
+
+/// The age is counted in whole units, so `-mtime 0` means "less than a day
+/// ago" and `-mtime +1` means "at least two days ago".
+pub fn is_match_time(
+    entry: &Entry,
+    field: TimeField,
+    cmp: Cmp,
+    unit: u64,
+    now: SystemTime,
+) -> bool {
+    let Some(time) = entry_time(entry, field) else {
+        return false;
+    };
+    let age = now.duration_since(time).unwrap_or(Duration::ZERO);
+    cmp.matches(age.as_secs() / unit)
+}
+
+pub fn is_match_newer(entry: &Entry, time: SystemTime) -> bool {
+    entry
+        .metadata()
+        .ok()
+        .and_then(|m| m.modified().ok())
+        .is_some_and(|modified| modified > time)
+}
+
+pub fn is_match_perm(entry: &Entry, perm: Perm) -> bool {
+    let Some(mode) = entry_mode(entry) else {
+        return false;
+    };
+    match perm {
+        Perm::Exact(bits) => mode == bits,
+        Perm::All(bits) => mode & bits == bits,
+        Perm::Any(bits) => bits == 0 || mode & bits != 0,
+    }
+}
+
+pub fn is_match_user(entry: &Entry, uid: u32) -> bool {
+    entry_owner(entry).is_some_and(|(u, _)| u == uid)
+}
+
+pub fn is_match_group(entry: &Entry, gid: u32) -> bool {
+    entry_owner(entry).is_some_and(|(_, g)| g == gid)
+}
+
+/// Matches empty regular files and directories without entries.
+pub fn is_match_empty(entry: &Entry) -> bool {
+    let file_type = entry.file_type();
+    if file_type.is_dir() {
+        fs::read_dir(entry.path()).is_ok_and(|mut d| d.next().is_none())
+    } else if file_type.is_file() {
+        entry.metadata().is_ok_and(|m| m.len() == 0)
+    } else {
+        false
+    }
+}
+
+/// Symbolic links are checked on the side `-type` does not look at: the
+/// target, or the link itself when links are followed. A broken link is a
+/// link either way.
+pub fn is_match_xtype(entry: &Entry, entry_types: &[EntryType]) -> bool {
+    let mut file_type = entry.file_type();
+    if entry.path_is_symlink() {
+        let meta = if file_type.is_symlink() {
+            fs::metadata(entry.path())
+        } else {
+            fs::symlink_metadata(entry.path())
+        };
+        if let Ok(meta) = meta {
+            file_type = meta.file_type();
+        }
+    }
+    EntryType::of(file_type).is_some_and(|t| entry_types.contains(&t))
+}
+
+#[cfg(unix)]
+pub fn is_match_access(entry: &Entry, access: Access) -> bool {
+    use rustix::fs::Access as Mode;
+
+    let mode = match access {
+        Access::Read => Mode::READ_OK,
+        Access::Write => Mode::WRITE_OK,
+        Access::Execute => Mode::EXEC_OK,
+    };
+    rustix::fs::access(entry.path(), mode).is_ok()
+}
+
+#[cfg(not(unix))]
+pub fn is_match_access(entry: &Entry, access: Access) -> bool {
+    match access {
+        Access::Read => entry.metadata().is_ok(),
+        Access::Write => entry.metadata().is_ok_and(|m| !m.permissions().readonly()),
+        Access::Execute => entry.file_type().is_dir(),
+    }
+}
+
+/// Searches regular files line by line, stopping at the first match. Lines
+/// are matched without their `\n` or `\r\n`, so that `$` matches at their
+/// end. Files that cannot be read do not match.
+pub fn is_match_contains(entry: &Entry, regex: &regex::bytes::Regex) -> bool {
+    if !entry.file_type().is_file() {
+        return false;
+    }
+    let Ok(file) = File::open(entry.path()) else {
+        return false;
+    };
+    let mut reader = BufReader::new(file);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        match reader.read_until(b'\n', &mut line) {
+            Ok(0) | Err(_) => return false,
+            Ok(_) => {
+                let text = line.strip_suffix(b"\n").unwrap_or(&line);
+                let text = text.strip_suffix(b"\r").unwrap_or(text);
+                if regex.is_match(text) {
+                    return true;
+                }
+            }
+        }
+    }
+}
+
+pub(crate) fn entry_time(entry: &Entry, field: TimeField) -> Option<SystemTime> {
+    meta_time(&entry.metadata().ok()?, field)
+}
+
+pub(crate) fn meta_time(meta: &fs::Metadata, field: TimeField) -> Option<SystemTime> {
+    match field {
+        TimeField::Access => meta.accessed().ok(),
+        TimeField::Modify => meta.modified().ok(),
+        TimeField::Change => change_time(meta),
+    }
+}
+
+#[cfg(unix)]
+fn change_time(meta: &fs::Metadata) -> Option<SystemTime> {
+    let secs = u64::try_from(meta.ctime()).ok()?;
+    let nanos = u32::try_from(meta.ctime_nsec()).ok()?;
+    Some(SystemTime::UNIX_EPOCH + Duration::new(secs, nanos))
+}
+
+#[cfg(not(unix))]
+fn change_time(meta: &fs::Metadata) -> Option<SystemTime> {
+    meta.created().ok()
+}
+
+pub(crate) fn entry_mode(entry: &Entry) -> Option<u32> {
+    meta_mode(&entry.metadata().ok()?)
+}
+
+#[cfg(unix)]
+pub(crate) fn meta_mode(meta: &fs::Metadata) -> Option<u32> {
+    Some(meta.mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn meta_mode(_meta: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+#[cfg(unix)]
+pub(crate) fn entry_owner(entry: &Entry) -> Option<(u32, u32)> {
+    entry.metadata().ok().map(|m| (m.uid(), m.gid()))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn entry_owner(_entry: &Entry) -> Option<(u32, u32)> {
+    None
+}
+
+#[cfg(unix)]
+fn lookup_user(name: &str) -> Option<u32> {
+    name.parse()
+        .ok()
+        .or_else(|| uzers::get_user_by_name(name).map(|u| u.uid()))
+}
+
+#[cfg(not(unix))]
+fn lookup_user(_name: &str) -> Option<u32> {
+    None
+}
+
+#[cfg(unix)]
+fn lookup_group(name: &str) -> Option<u32> {
+    name.parse()
+        .ok()
+        .or_else(|| uzers::get_group_by_name(name).map(|g| g.gid()))
+}
+
+#[cfg(not(unix))]
+fn lookup_group(_name: &str) -> Option<u32> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Access, Cmp, Perm, Predicate, TimeField};
+    use crate::{EntryType, RegexType, walk::Entry};
+    use ignore::WalkBuilder;
+    use std::{
+        fs::{self, File},
+        time::{Duration, SystemTime},
+    };
+    use tempfile::TempDir;
+
+    fn entry(path: &std::path::Path) -> Entry {
+        let entry = WalkBuilder::new(path).build().next().unwrap().unwrap();
+        Entry::try_from(entry).unwrap()
+    }
+
+    #[test]
+    fn test_parse_cmp() {
+        assert_eq!(Cmp::parse("5"), Some((Cmp::Exactly(5), "")));
+        assert_eq!(Cmp::parse("+5k"), Some((Cmp::Greater(5), "k")));
+        assert_eq!(Cmp::parse("-10"), Some((Cmp::Less(10), "")));
+        assert_eq!(Cmp::parse("k"), None);
+        assert_eq!(Cmp::parse("+"), None);
+    }
+
+    #[test]
+    fn test_parse_predicate() {
+        let now = SystemTime::now();
+        assert!(matches!(
+            Predicate::parse("-size", Some("+2k"), now, RegexType::Rust),
+            Ok(Predicate::Size {
+                cmp: Cmp::Greater(2),
+                unit: 1024
+            })
+        ));
+        assert!(matches!(
+            Predicate::parse("-mmin", Some("-30"), now, RegexType::Rust),
+            Ok(Predicate::Time {
+                field: TimeField::Modify,
+                cmp: Cmp::Less(30),
+                unit: 60,
+                ..
+            })
+        ));
+        assert!(matches!(
+            Predicate::parse("-perm", Some("/111"), now, RegexType::Rust),
+            Ok(Predicate::Perm(Perm::Any(0o111)))
+        ));
+        assert!(matches!(
+            Predicate::parse("-type", Some("f,l"), now, RegexType::Rust),
+            Ok(Predicate::Type(t)) if t == [EntryType::File, EntryType::Link]
+        ));
+
+        let err = |name, arg| Predicate::parse(name, arg, now, RegexType::Rust).unwrap_err();
+        assert_eq!(
+            err("-size", Some("10x")),
+            "invalid argument '10x' to '-size'"
+        );
+        assert_eq!(
+            err("-perm", Some("u+x")),
+            "invalid argument 'u+x' to '-perm'"
+        );
+        assert_eq!(
+            err("-type", Some("f,x")),
+            "invalid argument 'f,x' to '-type'"
+        );
+        assert_eq!(err("-mtime", None), "missing argument to '-mtime'");
+        assert_eq!(
+            err("-name", Some("[a")),
+            "invalid argument '[a' to '-name': error parsing glob '[a': unclosed character class; missing ']'"
+        );
+    }
+
+    #[test]
+    fn test_size() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("file");
+        fs::write(&path, vec![0; 1500]).unwrap();
+        let e = entry(&path);
+
+        let size =
+            |a| Predicate::parse("-size", Some(a), SystemTime::now(), RegexType::Rust).unwrap();
+        assert!(size("1500c").is_match(&e));
+        assert!(size("2k").is_match(&e));
+        assert!(size("+1k").is_match(&e));
+        assert!(!size("-2k").is_match(&e));
+        assert!(size("3").is_match(&e));
+    }
+
+    #[test]
+    fn test_time() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("file");
+        let file = File::create(&path).unwrap();
+        let now = SystemTime::now();
+        file.set_modified(now - Duration::from_secs(3 * 24 * 60 * 60 + 10))
+            .unwrap();
+        let e = entry(&path);
+
+        let mtime = |a| Predicate::parse("-mtime", Some(a), now, RegexType::Rust).unwrap();
+        assert!(mtime("3").is_match(&e));
+        assert!(mtime("+2").is_match(&e));
+        assert!(!mtime("-3").is_match(&e));
+        assert!(
+            Predicate::parse("-mmin", Some("+60"), now, RegexType::Rust)
+                .unwrap()
+                .is_match(&e)
+        );
+        assert!(Predicate::Newer(now - Duration::from_secs(4 * 24 * 60 * 60)).is_match(&e));
+        assert!(!Predicate::Newer(now).is_match(&e));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_perm() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("file");
+        File::create(&path).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o754)).unwrap();
+        let e = entry(&path);
+
+        assert!(Predicate::Perm(Perm::Exact(0o754)).is_match(&e));
+        assert!(!Predicate::Perm(Perm::Exact(0o755)).is_match(&e));
+        assert!(Predicate::Perm(Perm::All(0o700)).is_match(&e));
+        assert!(!Predicate::Perm(Perm::All(0o111)).is_match(&e));
+        assert!(Predicate::Perm(Perm::Any(0o111)).is_match(&e));
+        assert!(!Predicate::Perm(Perm::Any(0o002)).is_match(&e));
+    }
+
+    #[test]
+    fn test_empty() {
+        let dir = TempDir::new().unwrap();
+        let empty_dir = dir.path().join("empty");
+        fs::create_dir(&empty_dir).unwrap();
+        let empty_file = dir.path().join("empty.txt");
+        File::create(&empty_file).unwrap();
+        let file = dir.path().join("file.txt");
+        fs::write(&file, "x").unwrap();
+
+        assert!(Predicate::Empty.is_match(&entry(&empty_dir)));
+        assert!(Predicate::Empty.is_match(&entry(&empty_file)));
+        assert!(!Predicate::Empty.is_match(&entry(&file)));
+        assert!(!Predicate::Empty.is_match(&entry(dir.path())));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_xtype() {
+        use std::os::unix::fs::symlink;
+
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("file");
+        File::create(&file).unwrap();
+        let link = dir.path().join("link");
+        symlink(&file, &link).unwrap();
+        let broken = dir.path().join("broken");
+        symlink(dir.path().join("missing"), &broken).unwrap();
+        // Search paths are always followed, so look at the links from
+        // their directory.
+        let child = |name, follow| {
+            WalkBuilder::new(dir.path())
+                .follow_links(follow)
+                .build()
+                .filter_map(Result::ok)
+                .find(|e| e.file_name() == name)
+                .map(|e| Entry::try_from(e).unwrap())
+                .unwrap()
+        };
+        let xtype = |t| Predicate::XType(vec![t]);
+
+        assert!(xtype(EntryType::File).is_match(&child("link", false)));
+        assert!(!xtype(EntryType::Link).is_match(&child("link", false)));
+        assert!(xtype(EntryType::Link).is_match(&child("link", true)));
+        assert!(xtype(EntryType::Link).is_match(&child("broken", false)));
+        assert!(xtype(EntryType::File).is_match(&child("file", false)));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_executable() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new().unwrap();
+        let script = dir.path().join("script.sh");
+        fs::write(&script, "#!/bin/sh").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        let text = dir.path().join("notes.txt");
+        fs::write(&text, "notes").unwrap();
+        fs::set_permissions(&text, fs::Permissions::from_mode(0o644)).unwrap();
+        let executable = Predicate::Access(Access::Execute);
+
+        assert!(executable.is_match(&entry(&script)));
+        assert!(!executable.is_match(&entry(&text)));
+        assert!(executable.is_match(&entry(dir.path())));
+    }
+
+    #[test]
+    fn test_contains() {
+        let dir = TempDir::new().unwrap();
+        let todo = dir.path().join("todo.txt");
+        fs::write(&todo, "first\n// TODO: more\n").unwrap();
+        let done = dir.path().join("done.txt");
+        fs::write(&done, "first\nTO\nDO\n").unwrap();
+        let crlf = dir.path().join("crlf.txt");
+        fs::write(&crlf, "hello TODO\r\nbye\r\n").unwrap();
+        let contains =
+            |a| Predicate::parse("-contains", Some(a), SystemTime::now(), RegexType::Rust);
+
+        assert!(contains("TODO").unwrap().is_match(&entry(&todo)));
+        assert!(contains("^// TODO:").unwrap().is_match(&entry(&todo)));
+        assert!(contains("more$").unwrap().is_match(&entry(&todo)));
+        assert!(contains("TODO$").unwrap().is_match(&entry(&crlf)));
+        assert!(!contains("(?s)first.TO").unwrap().is_match(&entry(&done)));
+        assert!(!contains("TODO").unwrap().is_match(&entry(&done)));
+        assert!(!contains("TO.DO").unwrap().is_match(&entry(&done)));
+        assert!(!contains("first").unwrap().is_match(&entry(dir.path())));
+        assert!(
+            contains("(")
+                .unwrap_err()
+                .starts_with("invalid argument '(' to '-contains': regex parse error")
+        );
+    }
+}
@@ -0,0 +1,376 @@
+use std::{
+    ffi::OsStr,
+    fs::{self, FileType, Metadata},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
+    thread,
+    time::SystemTime,
+};
+
+use ignore::{DirEntry, WalkBuilder, WalkState};
+
+use crate::{
+    Config, SortKey,
+    action::{Context, io_message},
+    expr::Expr,
+};
+
+/// A directory entry found by the sequential or the parallel walker.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    path: PathBuf,
+    file_type: FileType,
+    depth: usize,
+    /// Set when the path is a symbolic link that was followed, so that the
+    /// metadata describes the target.
+    followed: bool,
+}
+
+impl Entry {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn file_name(&self) -> &OsStr {
+        self.path.file_name().unwrap_or(self.path.as_os_str())
+    }
+
+    pub fn file_type(&self) -> FileType {
+        self.file_type
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Returns true if the path is a symbolic link, whether or not it was
+    /// followed.
+    pub fn path_is_symlink(&self) -> bool {
+        self.followed || self.file_type.is_symlink()
+    }
+
+    pub fn metadata(&self) -> io::Result<Metadata> {
+        if self.followed {
+            fs::metadata(&self.path)
+        } else {
+            fs::symlink_metadata(&self.path)
+        }
+    }
+}
+
+impl TryFrom<DirEntry> for Entry {
+    type Error = DirEntry;
+
+    // Fails only for the stdin entry, which the walker never yields for a
+    // path.
+    fn try_from(entry: DirEntry) -> Result<Self, Self::Error> {
+        let Some(file_type) = entry.file_type() else {
+            return Err(entry);
+        };
+        Ok(Entry {
+            followed: entry.path_is_symlink() && !file_type.is_symlink(),
+            file_type,
+            depth: entry.depth(),
+            path: entry.into_path(),
+        })
+    }
+}
+
+// Configures the walk of one search path. Unless --respect-ignore is given
+// every entry is visited, as find does.
+fn builder(config: &Config, root: &Path) -> WalkBuilder {
+    let mut builder = WalkBuilder::new(root);
+    builder
+        .standard_filters(config.respect_ignore)
+        .hidden(config.respect_ignore && !config.hidden)
+        .max_depth(config.maxdepth)
+        .follow_links(config.follow)
+        .same_file_system(config.xdev);
+    builder
+}
+
+fn context(config: &Config) -> Context {
+    let mut ctx = Context::default();
+    ctx.relative_to.clone_from(&config.relative_to);
+    ctx.json = config.json;
+    ctx.hold_output = config.sort.is_some();
+    ctx
+}
+
+// Reports the errors of the walker, including problems with ignore files
+// that did not stop the entry from being visited.
+fn to_entry(result: Result<DirEntry, ignore::Error>, ctx: &mut Context) -> Option<Entry> {
+    match result {
+        Ok(entry) => {
+            if let Some(e) = entry.error() {
+                report(e, ctx);
+            }
+            Entry::try_from(entry).ok()
+        }
+        Err(e) => broken_link(&e).or_else(|| {
+            report(&e, ctx);
+            None
+        }),
+    }
+}
+
+// When following links, the walker reports links to missing files as errors,
+// while find visits them as links.
+fn broken_link(err: &ignore::Error) -> Option<Entry> {
+    let (mut path, mut depth) = (None, None);
+    let mut err = err;
+    loop {
+        match err {
+            ignore::Error::WithPath { path: p, err: e } => {
+                path = Some(p);
+                err = e;
+            }
+            ignore::Error::WithDepth { depth: d, err: e } => {
+                depth = Some(*d);
+                err = e;
+            }
+            _ => break,
+        }
+    }
+    let path = path?;
+    let file_type = fs::symlink_metadata(path).ok()?.file_type();
+    if !file_type.is_symlink() || path.exists() {
+        return None;
+    }
+    Some(Entry {
+        path: path.clone(),
+        file_type,
+        depth: depth?,
+        followed: false,
+    })
+}
+
+// Reports an error in the style of find, e.g. `findr: dir: Permission denied`.
+fn report(err: &ignore::Error, ctx: &mut Context) {
+    match err {
+        ignore::Error::Partial(errs) => errs.iter().for_each(|e| report(e, ctx)),
+        ignore::Error::WithDepth { err, .. } => report(err, ctx),
+        ignore::Error::WithPath { path, err } => {
+            ctx.error(format!("{}: {}", path.display(), describe(err)))
+        }
+        err => ctx.error(describe(err)),
+    }
+}
+
+fn describe(err: &ignore::Error) -> String {
+    match err {
+        ignore::Error::WithDepth { err, .. } => describe(err),
+        ignore::Error::Io(e) => io_message(e),
+        err => err.to_string(),
+    }
+}
+
+/// Walks the search paths with a single thread, writing output as soon as
+/// it is produced unless it is sorted. With `contents_first` the contents of
+/// a directory are visited before the directory itself, and `-prune` has no
+/// effect. Returns false if an error was reported.
+pub fn sequential(
+    config: &Config,
+    expression: Option<&Expr>,
+    print: bool,
+    contents_first: bool,
+) -> bool {
+    let min_depth = config.mindepth.unwrap_or(0);
+    let mut ctx = context(config);
+    let mut records = Vec::new();
+    let process = |entry: &Entry, ctx: &mut Context, records: &mut Vec<Record>| {
+        crate::visit(entry, expression, print, ctx);
+        if config.sort.is_none() {
+            ctx.write_output();
+        } else if !ctx.out.is_empty() {
+            let out = std::mem::take(&mut ctx.out);
+            records.push(Record::new(entry, out, config.sort));
+        }
+    };
+
+    for root in &config.paths {
+        ctx.root.clone_from(root);
+        // Directories whose contents are still being visited.
+        let mut pending: Vec<Entry> = Vec::new();
+        // The walker of one thread is the one that can skip a directory as
+        // it is visited. Its visitors are only ever used one at a time.
+        let state = Mutex::new((&mut ctx, &mut records, &mut pending));
+        builder(config, root).threads(1).build_parallel().run(|| {
+            Box::new(|result| {
+                let (ctx, records, pending) = &mut *state.lock().unwrap();
+                let Some(entry) = to_entry(result, ctx) else {
+                    return WalkState::Continue;
+                };
+                if entry.depth() < min_depth {
+                    return WalkState::Continue;
+                }
+                if contents_first {
+                    while let Some(dir) =
+                        pending.pop_if(|dir| !entry.path().starts_with(dir.path()))
+                    {
+                        process(&dir, ctx, records);
+                    }
+                    if entry.file_type().is_dir() {
+                        pending.push(entry);
+                    } else {
+                        process(&entry, ctx, records);
+                    }
+                    return WalkState::Continue;
+                }
+                process(&entry, ctx, records);
+                if ctx.prune && entry.file_type().is_dir() {
+                    WalkState::Skip
+                } else {
+                    WalkState::Continue
+                }
+            })
+        });
+        while let Some(dir) = pending.pop() {
+            process(&dir, &mut ctx, &mut records);
+        }
+        sort_records(&mut records, config.reverse);
+        write_records(records.drain(..));
+    }
+    ctx.finish();
+    !ctx.failed()
+}
+
+// Number of entries with output a worker collects before handing them to the
+// writer.
+const BATCH: usize = 256;
+
+/// Walks each search path with `threads` threads that steal directories from
+/// each other, evaluating the expression as entries are found. Unless the
+/// output is sorted, it appears in the order the entries are visited.
+/// Returns false if an error was reported.
+pub fn parallel(config: &Config, expression: Option<&Expr>, print: bool, threads: usize) -> bool {
+    let min_depth = config.mindepth.unwrap_or(0);
+    let failed = AtomicBool::new(false);
+
+    for root in &config.paths {
+        let walker = builder(config, root).threads(threads).build_parallel();
+        let (tx, rx) = mpsc::channel();
+
+        thread::scope(|s| {
+            s.spawn(|| write_results(rx, config.sort.is_some(), config.reverse));
+            walker.run(|| {
+                let mut worker = Worker {
+                    ctx: context(config),
+                    tx: tx.clone(),
+                    batch: Vec::new(),
+                    failed: &failed,
+                };
+                worker.ctx.root.clone_from(root);
+                Box::new(move |result| {
+                    let ctx = &mut worker.ctx;
+                    let Some(entry) = to_entry(result, ctx) else {
+                        return WalkState::Continue;
+                    };
+                    if entry.depth() < min_depth {
+                        return WalkState::Continue;
+                    }
+                    crate::visit(&entry, expression, print, ctx);
+                    let state = if ctx.prune && entry.file_type.is_dir() {
+                        WalkState::Skip
+                    } else {
+                        WalkState::Continue
+                    };
+                    if !ctx.out.is_empty() {
+                        let out = std::mem::take(&mut ctx.out);
+                        worker.push(Record::new(&entry, out, config.sort));
+                    }
+                    state
+                })
+            });
+            drop(tx);
+        });
+    }
+    !failed.into_inner()
+}
+
+// The state of one thread of the parallel walk.
+struct Worker<'a> {
+    ctx: Context,
+    tx: mpsc::Sender<Vec<Record>>,
+    batch: Vec<Record>,
+    failed: &'a AtomicBool,
+}
+
+impl Worker<'_> {
+    fn push(&mut self, record: Record) {
+        self.batch.push(record);
+        if self.batch.len() >= BATCH {
+            let _ = self.tx.send(std::mem::take(&mut self.batch));
+        }
+    }
+}
+
+impl Drop for Worker<'_> {
+    fn drop(&mut self) {
+        if !self.batch.is_empty() {
+            let _ = self.tx.send(std::mem::take(&mut self.batch));
+        }
+        if self.ctx.failed() {
+            self.failed.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+// The output of one entry, with the value it is sorted by besides its path.
+struct Record {
+    key: u128,
+    path: PathBuf,
+    out: Vec<u8>,
+}
+
+impl Record {
+    fn new(entry: &Entry, out: Vec<u8>, sort: Option<SortKey>) -> Record {
+        let key = match sort {
+            Some(SortKey::Size) => entry.metadata().map_or(0, |m| m.len().into()),
+            Some(SortKey::Mtime) => entry
+                .metadata()
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_nanos()),
+            Some(SortKey::Name) | None => 0,
+        };
+        Record {
+            key,
+            path: entry.path.clone(),
+            out,
+        }
+    }
+}
+
+fn sort_records(records: &mut [Record], reverse: bool) {
+    records.sort_unstable_by(|a, b| (a.key, &a.path).cmp(&(b.key, &b.path)));
+    if reverse {
+        records.reverse();
+    }
+}
+
+fn write_records(records: impl IntoIterator<Item = Record>) {
+    let mut stdout = io::BufWriter::new(io::stdout().lock());
+    for record in records {
+        let _ = stdout.write_all(&record.out);
+    }
+    let _ = stdout.flush();
+}
+
+// Writes the output of the workers as it arrives, or sorted once the walk is
+// done.
+fn write_results(rx: mpsc::Receiver<Vec<Record>>, sorted: bool, reverse: bool) {
+    if sorted {
+        let mut records: Vec<_> = rx.into_iter().flatten().collect();
+        sort_records(&mut records, reverse);
+        write_records(records);
+    } else {
+        write_records(rx.into_iter().flatten());
+    }
+}
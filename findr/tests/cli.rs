@@ -26,11 +26,12 @@ fn gen_bad_file() -> String {
 #[test]
 fn skips_bad_dir() -> Result<()> {
     let bad = gen_bad_file();
-    let expected = format!("{}: .* [(]os error [23][)]", &bad);
+    let expected = format!("^findr: {}: [^(]+\n$", &bad);
     Command::cargo_bin(PRG)?
-        .arg(&bad)
+        .args([&bad, "tests/inputs/f"])
         .assert()
-        .success()
+        .failure()
+        .stdout(predicate::str::contains("f.txt"))
         .stderr(predicate::str::is_match(expected)?);
     Ok(())
 }
@@ -39,35 +40,35 @@ fn skips_bad_dir() -> Result<()> {
 #[test]
 fn dies_bad_name() -> Result<()> {
     Command::cargo_bin(PRG)?
-        .args(["--name", "*.csv"])
+        .args(["--name", "[a"])
         .assert()
         .failure()
-        .stderr(predicate::str::contains("error: invalid value '*.csv'"));
+        .stderr(predicate::str::starts_with("findr: invalid value '[a'"));
     Ok(())
 }
 
 // --------------------------------------------------
 #[test]
 fn dies_bad_type() -> Result<()> {
-    let expected = "error: invalid value 'x' for '--type [<TYPE>...]'";
+    let expected = "findr: invalid value 'x' for '--type [<TYPE>...]'";
     Command::cargo_bin(PRG)?
         .args(["--type", "x"])
         .assert()
-        .failure()
-        .stderr(predicate::str::contains(expected));
+        .code(1)
+        .stderr(predicate::str::starts_with(expected));
     Ok(())
 }
 
 // --------------------------------------------------
 #[cfg(windows)]
-fn format_file_name(expected_file: &str) -> Cow<str> {
+fn format_file_name(expected_file: &str) -> Cow<'_, str> {
     // Equivalent to: Cow::Owned(format!("{}.windows", expected_file))
     format!("{}.windows", expected_file).into()
 }
 
 // --------------------------------------------------
 #[cfg(not(windows))]
-fn format_file_name(expected_file: &str) -> Cow<str> {
+fn format_file_name(expected_file: &str) -> Cow<'_, str> {
     // Equivalent to: Cow::Borrowed(expected_file)
     expected_file.into()
 }
@@ -226,7 +227,7 @@ fn type_f_l() -> Result<()> {
 #[test]
 fn name_csv() -> Result<()> {
     run(
-        &["tests/inputs", "-n", ".*[.]csv"],
+        &["tests/inputs", "-n", "*.csv"],
         "tests/expected/name_csv.txt",
     )
 }
@@ -235,7 +236,7 @@ fn name_csv() -> Result<()> {
 #[test]
 fn name_csv_mp3() -> Result<()> {
     run(
-        &["tests/inputs", "-n", ".*[.]csv", "-n", ".*[.]mp3"],
+        &["tests/inputs", "-n", "*.csv", "-n", "*.mp3"],
         "tests/expected/name_csv_mp3.txt",
     )
 }
@@ -244,7 +245,7 @@ fn name_csv_mp3() -> Result<()> {
 #[test]
 fn name_txt_path_a_d() -> Result<()> {
     run(
-        &["tests/inputs/a", "tests/inputs/d", "--name", ".*.txt"],
+        &["tests/inputs/a", "tests/inputs/d", "--name", "*.txt"],
         "tests/expected/name_txt_path_a_d.txt",
     )
 }
@@ -252,14 +253,14 @@ fn name_txt_path_a_d() -> Result<()> {
 // --------------------------------------------------
 #[test]
 fn name_a() -> Result<()> {
-    run(&["tests/inputs", "-n", "a"], "tests/expected/name_a.txt")
+    run(&["tests/inputs", "-n", "*a*"], "tests/expected/name_a.txt")
 }
 
 // --------------------------------------------------
 #[test]
 fn type_f_name_a() -> Result<()> {
     run(
-        &["tests/inputs", "-t", "f", "-n", "a"],
+        &["tests/inputs", "-t", "f", "-n", "*a*"],
         "tests/expected/type_f_name_a.txt",
     )
 }
@@ -268,7 +269,7 @@ fn type_f_name_a() -> Result<()> {
 #[test]
 fn type_d_name_a() -> Result<()> {
     run(
-        &["tests/inputs", "--type", "d", "--name", "a"],
+        &["tests/inputs", "--type", "d", "--name", "*a*"],
         "tests/expected/type_d_name_a.txt",
     )
 }
@@ -279,6 +280,510 @@ fn path_g() -> Result<()> {
     run(&["tests/inputs/g.csv"], "tests/expected/path_g.txt")
 }
 
+// --------------------------------------------------
+#[test]
+fn iname_csv() -> Result<()> {
+    run(
+        &["tests/inputs", "-iname", "*.CSV"],
+        "tests/expected/name_csv.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn path_glob() -> Result<()> {
+    run(
+        &["tests/inputs", "-path", "*/b/*"],
+        "tests/expected/path_b.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn regex_csv_mp3() -> Result<()> {
+    run(
+        &["tests/inputs", "-regex", r".*/[a-c]\.(csv|mp3)"],
+        "tests/expected/regex_csv_mp3.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn regex_pcre() -> Result<()> {
+    run(
+        &[
+            "tests/inputs",
+            "-regextype",
+            "pcre",
+            "-regex",
+            r".*/(?!d)\w\.txt",
+        ],
+        "tests/expected/regex_pcre_txt.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_regex() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "-regex", "("])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid argument '(' to '-regex'"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_size() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "-size", "10x"])
+        .assert()
+        .failure()
+        .stderr("findr: invalid argument '10x' to '-size'\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_missing_predicate_argument() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "-mtime"])
+        .assert()
+        .failure()
+        .stderr("findr: missing argument to '-mtime'\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_unknown_predicate() -> Result<()> {
+    for (args, expected) in [
+        (
+            &["tests/inputs", "-nam", "x"][..],
+            "findr: unknown predicate '-nam'\n",
+        ),
+        (
+            &["tests/inputs", "-bogus"],
+            "findr: unknown predicate '-bogus'\n",
+        ),
+        (
+            &["tests/inputs", "-type", "f", "foo"],
+            "findr: paths must precede expression: 'foo'\n",
+        ),
+    ] {
+        Command::cargo_bin(PRG)?
+            .args(args)
+            .assert()
+            .failure()
+            .stdout("")
+            .stderr(expected);
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn size_2c() -> Result<()> {
+    run(
+        &["tests/inputs", "-size", "2c"],
+        "tests/expected/type_f.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn size_name_a() -> Result<()> {
+    run(
+        &["tests/inputs", "-n", "*a*", "-size", "-2k"],
+        "tests/expected/type_f_name_a.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn expr_or() -> Result<()> {
+    run(
+        &["tests/inputs", "-name", "*a*", "-o", "-type", "d"],
+        "tests/expected/expr_name_a_or_type_d.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn expr_not_group() -> Result<()> {
+    run(
+        &[
+            "tests/inputs",
+            "!",
+            "(",
+            "-type",
+            "d",
+            "-or",
+            "-name",
+            "*.csv",
+            ")",
+        ],
+        "tests/expected/expr_not_group.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn expr_group_with_type_option() -> Result<()> {
+    run(
+        &[
+            "tests/inputs",
+            "-t",
+            "f",
+            "(",
+            "-name",
+            "*.mp3",
+            "-o",
+            "-name",
+            "*.tsv",
+            ")",
+        ],
+        "tests/expected/expr_type_f_group.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_unbalanced_parens() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "(", "-name", "*a*"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("missing ')' in expression"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_dangling_or() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "-name", "*a*", "-o"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "expected an expression after '-o'",
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn maxdepth() -> Result<()> {
+    run(
+        &["tests/inputs", "-maxdepth", "1"],
+        "tests/expected/maxdepth_1.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn mindepth_maxdepth() -> Result<()> {
+    run(
+        &[
+            "tests/inputs",
+            "--mindepth",
+            "2",
+            "--maxdepth",
+            "2",
+            "-t",
+            "d",
+        ],
+        "tests/expected/mindepth_2_maxdepth_2_type_d.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn prune() -> Result<()> {
+    run(
+        &[
+            "tests/inputs",
+            "-name",
+            "[ab]",
+            "-prune",
+            "-o",
+            "-type",
+            "f",
+        ],
+        "tests/expected/prune_a_b.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn threads() -> Result<()> {
+    run(&["tests/inputs", "-j", "4"], "tests/expected/path1.txt")
+}
+
+// --------------------------------------------------
+#[test]
+fn threads_prune() -> Result<()> {
+    run(
+        &[
+            "tests/inputs",
+            "--threads",
+            "4",
+            "-name",
+            "[ab]",
+            "-prune",
+            "-o",
+            "-type",
+            "f",
+        ],
+        "tests/expected/prune_a_b.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn threads_sort_name() -> Result<()> {
+    let file = format_file_name("tests/expected/path1.txt");
+    let contents = fs::read_to_string(file.as_ref())?;
+    let mut expected: Vec<&str> = contents.lines().collect();
+    expected.sort();
+
+    for threads in ["1", "4"] {
+        let cmd = Command::cargo_bin(PRG)?
+            .args(["tests/inputs", "-j", threads, "--sort", "name"])
+            .assert()
+            .success();
+        let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+        assert_eq!(stdout.lines().collect::<Vec<_>>(), expected);
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn follow_symlink_loop() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    fs::create_dir(dir.path().join("sub"))?;
+    std::os::unix::fs::symlink("..", dir.path().join("sub/up"))?;
+
+    let cmd = Command::cargo_bin(PRG)?
+        .args(["-L", dir.path().to_str().unwrap()])
+        .assert()
+        .failure();
+    let out = cmd.get_output();
+    let stdout = String::from_utf8(out.stdout.clone())?;
+    assert_eq!(stdout.lines().count(), 2);
+
+    let stderr = String::from_utf8(out.stderr.clone())?;
+    assert!(stderr.contains("File system loop found"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn print0() -> Result<()> {
+    let cmd = Command::cargo_bin(PRG)?
+        .args(["tests/inputs/a", "-type", "f", "-print0"])
+        .assert()
+        .success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+    let mut paths: Vec<String> = stdout
+        .split_terminator('\0')
+        .map(|p| p.replace('\\', "/"))
+        .collect();
+    paths.sort();
+    assert_eq!(
+        paths,
+        [
+            "tests/inputs/a/a.txt",
+            "tests/inputs/a/b/b.csv",
+            "tests/inputs/a/b/c/c.mp3"
+        ]
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn printf() -> Result<()> {
+    run(
+        &["tests/inputs/a", "-printf", r"%y %d %f\n"],
+        "tests/expected/printf_a.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_printf() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "-printf", "%q"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "unsupported format directive '%q'",
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn exec() -> Result<()> {
+    run(
+        &["tests/inputs", "-type", "f", "-exec", "echo", "{}", ";"],
+        "tests/expected/type_f.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn exec_false_is_false() -> Result<()> {
+    run(
+        &[
+            "tests/inputs",
+            "-exec",
+            "false",
+            ";",
+            "-o",
+            "-type",
+            "d",
+            "-print",
+        ],
+        "tests/expected/type_d.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn exec_batch() -> Result<()> {
+    let cmd = Command::cargo_bin(PRG)?
+        .args(["tests/inputs/d", "-type", "f", "-exec", "echo", "{}", "+"])
+        .assert()
+        .success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 1);
+    let mut paths: Vec<&str> = lines[0].split(' ').collect();
+    paths.sort();
+    assert_eq!(
+        paths,
+        [
+            "tests/inputs/d/d.tsv",
+            "tests/inputs/d/d.txt",
+            "tests/inputs/d/e/e.mp3"
+        ]
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn execdir() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs/a/b", "-name", "*.mp3", "-execdir", "pwd", ";"])
+        .assert()
+        .success()
+        .stdout(predicate::str::ends_with("tests/inputs/a/b/c\n"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn delete() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let keep = dir.path().join("keep.txt");
+    let remove = dir.path().join("remove");
+    fs::create_dir_all(remove.join("nested"))?;
+    fs::write(remove.join("nested/file.txt"), "x")?;
+    fs::write(&keep, "x")?;
+
+    Command::cargo_bin(PRG)?
+        .arg(&remove)
+        .arg("-delete")
+        .assert()
+        .success()
+        .stdout("");
+
+    assert!(!remove.exists());
+    assert!(keep.exists());
+    Ok(())
+}
+
+// --------------------------------------------------
+fn gen_ignore_tree() -> Result<tempfile::TempDir> {
+    let dir = tempfile::tempdir()?;
+    let root = dir.path();
+    for sub in [".git", ".hidden", "build", "sub"] {
+        fs::create_dir(root.join(sub))?;
+    }
+    fs::write(root.join(".gitignore"), "*.log\nbuild/\n")?;
+    fs::write(root.join(".ignore"), "secret.txt\n")?;
+    for file in [
+        ".hidden/x.txt",
+        "a.txt",
+        "b.log",
+        "build/out.txt",
+        "secret.txt",
+        "sub/c.log",
+        "sub/c.txt",
+    ] {
+        fs::write(root.join(file), "x")?;
+    }
+    Ok(dir)
+}
+
+// Runs findr from `dir` and returns the lines it printed.
+fn output_in(dir: &Path, args: &[&str]) -> Result<Vec<String>> {
+    let cmd = Command::cargo_bin(PRG)?
+        .current_dir(dir)
+        .args(args)
+        .assert()
+        .success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+    Ok(stdout.lines().map(|l| l.replace('\\', "/")).collect())
+}
+
+// Runs findr from `dir` and returns the sorted paths it printed.
+fn run_in(dir: &Path, args: &[&str]) -> Result<Vec<String>> {
+    let mut lines = output_in(dir, args)?;
+    lines.sort();
+    Ok(lines)
+}
+
+// --------------------------------------------------
+#[test]
+fn respect_ignore() -> Result<()> {
+    let dir = gen_ignore_tree()?;
+    for threads in ["1", "4"] {
+        let args = [".", "--respect-ignore", "-type", "f", "-j", threads];
+        assert_eq!(run_in(dir.path(), &args)?, ["./a.txt", "./sub/c.txt"]);
+    }
+
+    assert_eq!(run_in(dir.path(), &[".", "-type", "f"])?.len(), 9);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn respect_ignore_hidden() -> Result<()> {
+    let dir = gen_ignore_tree()?;
+    assert_eq!(
+        run_in(
+            dir.path(),
+            &[".", "--respect-ignore", "--hidden", "-name", "*.txt"]
+        )?,
+        ["./.hidden/x.txt", "./a.txt", "./sub/c.txt"]
+    );
+    Ok(())
+}
+
 // --------------------------------------------------
 #[test]
 #[cfg(not(windows))]
@@ -293,11 +798,9 @@ fn unreadable_dir() -> Result<()> {
         .status()
         .expect("failed");
 
-    let cmd = Command::cargo_bin(PRG)?
-        .arg("tests/inputs")
-        .assert()
-        .success();
+    let assert = Command::cargo_bin(PRG)?.arg("tests/inputs").assert();
     fs::remove_dir(dirname)?;
+    let cmd = assert.failure();
 
     let out = cmd.get_output();
     let stdout = String::from_utf8(out.stdout.clone())?;
@@ -306,6 +809,219 @@ fn unreadable_dir() -> Result<()> {
     assert_eq!(lines.len(), 17);
 
     let stderr = String::from_utf8(out.stderr.clone())?;
-    assert!(stderr.contains("cant-touch-this: Permission denied"));
+    assert!(stderr.contains("findr: tests/inputs/cant-touch-this: Permission denied\n"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn non_utf8_name() -> Result<()> {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    let dir = tempfile::tempdir()?;
+    let name = OsStr::from_bytes(b"bad\xff.txt");
+    fs::write(dir.path().join(name), "x")?;
+    let mut expected = b"./bad\xff.txt".to_vec();
+
+    for args in [
+        &["-name", "*.txt"][..],
+        &["-regex", r".*\.txt"],
+        &["-regex", r"\./bad(?-u:\xff)\.txt"],
+    ] {
+        Command::cargo_bin(PRG)?
+            .current_dir(dir.path())
+            .arg(".")
+            .args(args)
+            .assert()
+            .success()
+            .stdout(predicate::eq([&expected[..], b"\n"].concat()));
+    }
+
+    expected.extend_from_slice(b"|bad\xff.txt\n");
+    Command::cargo_bin(PRG)?
+        .current_dir(dir.path())
+        .args([".", "-type", "f", "-printf", r"%p|%f\n"])
+        .assert()
+        .success()
+        .stdout(predicate::eq(expected));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn xtype_broken_link() -> Result<()> {
+    run(
+        &["tests/inputs", "-L", "-xtype", "l"],
+        "tests/expected/type_l.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn executable_contains() -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir()?;
+    for (name, text, mode) in [
+        ("todo.sh", "#!/bin/sh\n# TODO: tests\n", 0o755),
+        ("done.sh", "#!/bin/sh\n", 0o755),
+        ("todo.txt", "TODO\n", 0o644),
+    ] {
+        let path = dir.path().join(name);
+        fs::write(&path, text)?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(mode))?;
+    }
+
+    assert_eq!(
+        run_in(
+            dir.path(),
+            &[".", "-type", "f", "-executable", "-contains", "TODO"]
+        )?,
+        ["./todo.sh"]
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn type_fifo() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    fs::write(dir.path().join("file"), "x")?;
+    let status = std::process::Command::new("mkfifo")
+        .arg(dir.path().join("fifo"))
+        .status()?;
+    assert!(status.success());
+
+    assert_eq!(run_in(dir.path(), &[".", "-type", "p"])?, ["./fifo"]);
+    assert_eq!(run_in(dir.path(), &[".", "--type", "p"])?, ["./fifo"]);
+    // Only regular files are searched, so the FIFO is never opened.
+    assert_eq!(
+        run_in(dir.path(), &[".", "-contains", "x", "-printf", "%y %f\n"])?,
+        ["f file"]
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn sort_size() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    for (name, size) in [("a.txt", 3), ("b.txt", 1), ("c.txt", 2), ("d.txt", 1)] {
+        fs::write(dir.path().join(name), "x".repeat(size))?;
+    }
+
+    for threads in ["1", "4"] {
+        let args = [".", "-type", "f", "--sort", "size", "-j", threads];
+        assert_eq!(
+            output_in(dir.path(), &args)?,
+            ["./b.txt", "./d.txt", "./c.txt", "./a.txt"]
+        );
+        let args = [
+            ".",
+            "-type",
+            "f",
+            "--sort",
+            "size",
+            "--reverse",
+            "-j",
+            threads,
+        ];
+        assert_eq!(
+            output_in(dir.path(), &args)?,
+            ["./a.txt", "./c.txt", "./d.txt", "./b.txt"]
+        );
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn sort_mtime() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let now = std::time::SystemTime::now();
+    for (name, age) in [("new", 0), ("old", 200), ("older", 300), ("recent", 100)] {
+        let file = fs::File::create(dir.path().join(name))?;
+        file.set_modified(now - std::time::Duration::from_secs(age))?;
+    }
+
+    assert_eq!(
+        output_in(dir.path(), &[".", "-type", "f", "--sort", "mtime"])?,
+        ["./older", "./old", "./recent", "./new"]
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_reverse_without_sort() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["tests/inputs", "--reverse"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--sort <KEY>"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn relative_to() -> Result<()> {
+    let dir = gen_ignore_tree()?;
+    assert_eq!(
+        run_in(dir.path(), &["sub", "--relative-to", "build"])?,
+        ["../sub", "../sub/c.log", "../sub/c.txt"]
+    );
+    assert_eq!(
+        run_in(
+            dir.path(),
+            &["./sub/../sub", "--relative-to", "sub", "-printf", "%p %h\n"]
+        )?,
+        [". .", "c.log .", "c.txt ."]
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn absolute() -> Result<()> {
+    let dir = gen_ignore_tree()?;
+    let root = dir.path().canonicalize()?.join("sub");
+    let root = root.to_string_lossy().replace('\\', "/");
+    assert_eq!(
+        run_in(dir.path(), &["sub", "--absolute", "-name", "*.txt"])?,
+        [format!("{}/c.txt", root)]
+    );
+
+    // `.` and `..` are resolved in the search path
+    assert_eq!(
+        run_in(
+            dir.path(),
+            &["./sub/../sub", "--absolute", "-name", "*.txt"]
+        )?,
+        [format!("{}/c.txt", root)]
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn json() -> Result<()> {
+    let dir = gen_ignore_tree()?;
+    let lines = output_in(dir.path(), &["sub", "--json", "--sort", "name"])?;
+    let objects = lines
+        .iter()
+        .map(|line| serde_json::from_str(line))
+        .collect::<Result<Vec<serde_json::Value>, _>>()?;
+
+    let paths: Vec<_> = objects.iter().map(|o| o["path"].as_str()).collect();
+    assert_eq!(paths, [Some("sub"), Some("sub/c.log"), Some("sub/c.txt")]);
+    assert_eq!(objects[0]["type"], "directory");
+    assert_eq!(objects[2]["type"], "file");
+    assert_eq!(objects[2]["size"], 1);
+    assert!(objects[2]["mtime"].as_f64().is_some_and(|t| t > 0.0));
+    #[cfg(unix)]
+    assert!(objects[2]["mode"].is_string());
     Ok(())
 }
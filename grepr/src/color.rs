@@ -0,0 +1,146 @@
+use std::ops::Range;
+
+use crate::pattern::splice;
+
+/// SGR codes used to highlight the parts of the output, in the format of
+/// GNU grep's `GREP_COLORS`. An empty code leaves the text as is.
+#[derive(Debug, Default, PartialEq)]
+pub struct Colors {
+    /// `ms`: matches in selected lines.
+    pub selected_match: String,
+    /// `mc`: matches in context lines.
+    pub context_match: String,
+    /// `fn`: file names.
+    pub filename: String,
+    /// `ln`: line numbers.
+    pub line_number: String,
+    /// `bn`: byte offsets.
+    pub byte_offset: String,
+    /// `se`: the `:` and `-` separators and the `--` between groups.
+    pub separator: String,
+    /// Cleared by `ne`: erase to the end of the line after each colored part.
+    erase: bool,
+}
+
+impl Colors {
+    /// The colors of GNU grep, changed by the `GREP_COLORS` environment
+    /// variable.
+    pub fn from_env() -> Colors {
+        let mut colors = Colors {
+            selected_match: "01;31".to_string(),
+            context_match: "01;31".to_string(),
+            filename: "35".to_string(),
+            line_number: "32".to_string(),
+            byte_offset: "32".to_string(),
+            separator: "36".to_string(),
+            erase: true,
+        };
+        if let Ok(spec) = std::env::var("GREP_COLORS") {
+            colors.update(&spec);
+        }
+        colors
+    }
+
+    // Applies a list such as `ms=01;32:fn=34:ne`. Unknown capabilities are
+    // ignored, as GNU grep does.
+    fn update(&mut self, spec: &str) {
+        for capability in spec.split(':') {
+            let (name, value) = capability.split_once('=').unwrap_or((capability, ""));
+            let value = value.to_string();
+            match name {
+                "mt" => {
+                    self.selected_match.clone_from(&value);
+                    self.context_match = value;
+                }
+                "ms" => self.selected_match = value,
+                "mc" => self.context_match = value,
+                "fn" => self.filename = value,
+                "ln" => self.line_number = value,
+                "bn" => self.byte_offset = value,
+                "se" => self.separator = value,
+                "ne" => self.erase = false,
+                _ => {}
+            }
+        }
+    }
+
+    // The escape sequences that start and end text painted with `code`.
+    fn sequences(&self, code: &str) -> (String, &'static str) {
+        if self.erase {
+            (format!("\x1b[{code}m\x1b[K"), "\x1b[m\x1b[K")
+        } else {
+            (format!("\x1b[{code}m"), "\x1b[m")
+        }
+    }
+
+    /// Wraps `text` in the escape sequences for `code`.
+    pub fn paint(&self, code: &str, text: &str) -> String {
+        if code.is_empty() || text.is_empty() {
+            return text.to_string();
+        }
+        let (start, end) = self.sequences(code);
+        format!("{start}{text}{end}")
+    }
+
+    /// Like `paint`, for text that need not be valid UTF-8.
+    pub fn paint_bytes(&self, code: &str, text: &[u8]) -> Vec<u8> {
+        if code.is_empty() || text.is_empty() {
+            return text.to_vec();
+        }
+        let (start, end) = self.sequences(code);
+        [start.as_bytes(), text, end.as_bytes()].concat()
+    }
+
+    /// Paints the non-empty `matches` in `text` with `code`. The text is
+    /// kept as bytes, so that it is printed unchanged.
+    pub fn highlight(
+        &self,
+        code: &str,
+        text: &[u8],
+        matches: impl Iterator<Item = Range<usize>>,
+    ) -> Vec<u8> {
+        if code.is_empty() {
+            return text.to_vec();
+        }
+        let parts = matches
+            .filter(|m| !m.is_empty())
+            .map(|m| (m.clone(), self.paint_bytes(code, &text[m])));
+        splice(text, parts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Colors;
+    use crate::pattern::Matcher;
+    use regex::bytes::Regex;
+
+    #[test]
+    fn test_update() {
+        let mut colors = Colors::from_env();
+        colors.update("mt=01;32:fn=:ln=33:bogus=1:ne");
+        assert_eq!(colors.selected_match, "01;32");
+        assert_eq!(colors.context_match, "01;32");
+        assert_eq!(colors.filename, "");
+        assert_eq!(colors.line_number, "33");
+        assert!(!colors.erase);
+    }
+
+    #[test]
+    fn test_highlight() {
+        let colors = Colors::from_env();
+        let re = Matcher::Rust(Regex::new("o|x*").unwrap());
+        assert_eq!(
+            colors.highlight("01;31", b"f\xffoo", re.find_iter(b"f\xffoo")),
+            b"f\xff\x1b[01;31m\x1b[Ko\x1b[m\x1b[K\x1b[01;31m\x1b[Ko\x1b[m\x1b[K"
+        );
+
+        // Without colors the text is unchanged
+        let colors = Colors::default();
+        assert_eq!(
+            colors.highlight(&colors.selected_match, b"foo", re.find_iter(b"foo")),
+            b"foo"
+        );
+        assert_eq!(colors.paint(&colors.filename, "name"), "name");
+    }
+}
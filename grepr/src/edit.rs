@@ -0,0 +1,217 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use tempfile::NamedTempFile;
+
+use crate::{BinaryFiles, Config, count_lines, pattern::Matcher};
+
+/// The outcome of `edit` for one file.
+#[derive(Debug, Default)]
+pub struct Edit {
+    /// Whether a line matched, even if the replacement left it unchanged.
+    pub selected: bool,
+    /// The changes as a unified diff, with `--dry-run`.
+    pub diff: Vec<u8>,
+}
+
+/// A line changed by `edit`.
+#[derive(Debug, PartialEq)]
+struct Change<'a> {
+    /// Starting from 1, in the original file.
+    line_number: usize,
+    old: &'a [u8],
+    /// May span several lines if the template has newlines.
+    new: Vec<u8>,
+}
+
+/// Replaces the matches in the matching lines of the file at `path` with
+/// `template`, up to `-m` lines. The new contents are written to a temporary
+/// file which then takes the place of the original, so that the file is
+/// never left half written. Binary files are left alone unless `-a` is
+/// given.
+pub fn edit(config: &Config, pattern: &Matcher, template: &str, path: &Path) -> io::Result<Edit> {
+    let data = fs::read(path)?;
+    // Unlike searching, any NUL byte makes a file binary, so that no part of
+    // a binary file is ever rewritten.
+    let text = config.text || config.binary_files == BinaryFiles::Text;
+    if !text && data.contains(&0) {
+        return Ok(Edit::default());
+    }
+
+    let mut selected = 0;
+    let mut changes = Vec::new();
+    for (i, line) in data.split_inclusive(|&b| b == b'\n').enumerate() {
+        let content = line.strip_suffix(b"\n").unwrap_or(line);
+        if config.max_count.is_some_and(|max| selected >= max) {
+            break;
+        }
+        if !pattern.is_match(content) {
+            continue;
+        }
+        selected += 1;
+        let mut new = pattern.replace_all(content, template);
+        new.extend_from_slice(&line[content.len()..]);
+        if new != line {
+            changes.push(Change {
+                line_number: i + 1,
+                old: line,
+                new,
+            });
+        }
+    }
+
+    let mut edit = Edit {
+        selected: selected > 0,
+        diff: Vec::new(),
+    };
+    if config.dry_run {
+        edit.diff = diff(&path.display().to_string(), &changes);
+    } else if !changes.is_empty() {
+        let contents = apply(&data, &changes);
+        write_atomically(path, &contents, config.backup.as_deref())?;
+    }
+    Ok(edit)
+}
+
+// Returns `data` with the changed lines replaced.
+fn apply(data: &[u8], changes: &[Change]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(data.len());
+    let mut changes = changes.iter().peekable();
+    for (i, line) in data.split_inclusive(|&b| b == b'\n').enumerate() {
+        match changes.next_if(|c| c.line_number == i + 1) {
+            Some(change) => result.extend_from_slice(&change.new),
+            None => result.extend_from_slice(line),
+        }
+    }
+    result
+}
+
+// Replaces the file at `path`, keeping its permissions. A symbolic link is
+// followed, so that the file it points to is changed rather than replaced
+// by a regular file. With `backup`, the original is first copied to a file
+// with that suffix.
+fn write_atomically(path: &Path, contents: &[u8], backup: Option<&str>) -> io::Result<()> {
+    let target = fs::canonicalize(path)?;
+    let dir = target.parent().unwrap_or_else(|| Path::new("."));
+    let mut file = NamedTempFile::new_in(dir)?;
+    file.write_all(contents)?;
+    file.as_file()
+        .set_permissions(fs::metadata(&target)?.permissions())?;
+    if let Some(suffix) = backup {
+        let mut backup = target.as_os_str().to_owned();
+        backup.push(suffix);
+        fs::copy(&target, backup)?;
+    }
+    file.persist(&target).map_err(|e| e.error)?;
+    Ok(())
+}
+
+// Formats the changes as a unified diff without context lines, with a hunk
+// for each run of changed lines.
+fn diff(path: &str, changes: &[Change]) -> Vec<u8> {
+    let mut out = Vec::new();
+    if changes.is_empty() {
+        return out;
+    }
+    out.extend(format!("--- {path}\n+++ {path}\n").as_bytes());
+    // How many lines the hunks so far added to the file.
+    let mut offset: isize = 0;
+    let mut start = 0;
+    while start < changes.len() {
+        let mut end = start + 1;
+        while end < changes.len() && changes[end].line_number == changes[end - 1].line_number + 1 {
+            end += 1;
+        }
+        let hunk = &changes[start..end];
+        let old_start = hunk[0].line_number;
+        let old_count = hunk.len();
+        let new_count: usize = hunk.iter().map(|c| count_lines(&c.new, b'\n')).sum();
+        let new_start = old_start.saturating_add_signed(offset);
+        out.extend(
+            format!(
+                "@@ -{} +{} @@\n",
+                range(old_start, old_count),
+                range(new_start, new_count)
+            )
+            .as_bytes(),
+        );
+        for change in hunk {
+            diff_lines(&mut out, b'-', change.old);
+        }
+        for change in hunk {
+            diff_lines(&mut out, b'+', &change.new);
+        }
+        offset += new_count as isize - old_count as isize;
+        start = end;
+    }
+    out
+}
+
+// Formats the lines of a hunk header, in the short form for one line.
+fn range(start: usize, count: usize) -> String {
+    match count {
+        0 => format!("{},0", start - 1),
+        1 => start.to_string(),
+        _ => format!("{start},{count}"),
+    }
+}
+
+// Writes `text` as removed or added lines, marking a missing final newline
+// the way diff does.
+fn diff_lines(out: &mut Vec<u8>, marker: u8, text: &[u8]) {
+    for line in text.split_inclusive(|&b| b == b'\n') {
+        out.push(marker);
+        out.extend_from_slice(line);
+        if !line.ends_with(b"\n") {
+            out.extend_from_slice(b"\n\\ No newline at end of file\n");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Change, apply, diff};
+
+    fn change(line_number: usize, old: &'static [u8], new: &[u8]) -> Change<'static> {
+        Change {
+            line_number,
+            old,
+            new: new.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_apply() {
+        let changes = [change(2, b"b\n", b"B\n"), change(3, b"c", b"C\nD")];
+        assert_eq!(apply(b"a\nb\nc", &changes), b"a\nB\nC\nD");
+    }
+
+    #[test]
+    fn test_diff() {
+        let changes = [
+            change(2, b"b\n", b"B\n"),
+            change(3, b"c\n", b"C\nC\n"),
+            change(7, b"g", b""),
+        ];
+        assert_eq!(
+            String::from_utf8(diff("f.txt", &changes)).unwrap(),
+            "--- f.txt\n\
+             +++ f.txt\n\
+             @@ -2,2 +2,3 @@\n\
+             -b\n\
+             -c\n\
+             +B\n\
+             +C\n\
+             +C\n\
+             @@ -7 +7,0 @@\n\
+             -g\n\
+             \\ No newline at end of file\n"
+        );
+
+        // Nothing is printed for files without changes
+        assert!(diff("f.txt", &[]).is_empty());
+    }
+}
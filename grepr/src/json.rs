@@ -0,0 +1,145 @@
+use std::{ops::Range, time::Duration};
+
+use base64::{Engine, prelude::BASE64_STANDARD};
+use serde_json::{Value, json};
+
+/// What was searched and found, for the `end` message of an input and the
+/// final `summary`, with the fields of ripgrep's statistics.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Stats {
+    pub elapsed: Duration,
+    pub searches: u64,
+    pub searches_with_match: u64,
+    pub bytes_searched: u64,
+    pub bytes_printed: u64,
+    pub matched_lines: u64,
+    pub matches: u64,
+}
+
+impl Stats {
+    pub fn add(&mut self, other: &Stats) {
+        self.elapsed += other.elapsed;
+        self.searches += other.searches;
+        self.searches_with_match += other.searches_with_match;
+        self.bytes_searched += other.bytes_searched;
+        self.bytes_printed += other.bytes_printed;
+        self.matched_lines += other.matched_lines;
+        self.matches += other.matches;
+    }
+
+    fn to_json(self) -> Value {
+        json!({
+            "elapsed": duration(self.elapsed),
+            "searches": self.searches,
+            "searches_with_match": self.searches_with_match,
+            "bytes_searched": self.bytes_searched,
+            "bytes_printed": self.bytes_printed,
+            "matched_lines": self.matched_lines,
+            "matches": self.matches,
+        })
+    }
+}
+
+fn duration(d: Duration) -> Value {
+    json!({
+        "secs": d.as_secs(),
+        "nanos": d.subsec_nanos(),
+        "human": format!("{:.6}s", d.as_secs_f64()),
+    })
+}
+
+// Text is given as a string if it is valid UTF-8, and in base64 otherwise.
+fn data(text: &[u8]) -> Value {
+    match std::str::from_utf8(text) {
+        Ok(text) => json!({ "text": text }),
+        Err(_) => json!({ "bytes": BASE64_STANDARD.encode(text) }),
+    }
+}
+
+/// Starts the messages about the input at `path`.
+pub fn begin(path: &str) -> Value {
+    json!({
+        "type": "begin",
+        "data": { "path": data(path.as_bytes()) },
+    })
+}
+
+/// A matching or context line, with the ranges of the matches in `text`.
+pub fn line(
+    is_match: bool,
+    path: &str,
+    text: &[u8],
+    line_number: usize,
+    absolute_offset: usize,
+    matches: &[Range<usize>],
+) -> Value {
+    let submatches: Vec<Value> = matches
+        .iter()
+        .map(|m| json!({ "match": data(&text[m.clone()]), "start": m.start, "end": m.end }))
+        .collect();
+    json!({
+        "type": if is_match { "match" } else { "context" },
+        "data": {
+            "path": data(path.as_bytes()),
+            "lines": data(text),
+            "line_number": line_number,
+            "absolute_offset": absolute_offset,
+            "submatches": submatches,
+        },
+    })
+}
+
+/// Ends the messages about the input at `path`.
+pub fn end(path: &str, stats: Stats) -> Value {
+    json!({
+        "type": "end",
+        "data": {
+            "path": data(path.as_bytes()),
+            "binary_offset": null,
+            "stats": stats.to_json(),
+        },
+    })
+}
+
+/// The last message, about all of the inputs.
+pub fn summary(elapsed: Duration, stats: Stats) -> Value {
+    json!({
+        "type": "summary",
+        "data": {
+            "elapsed_total": duration(elapsed),
+            "stats": stats.to_json(),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{data, line};
+    use serde_json::json;
+
+    #[test]
+    fn test_data() {
+        assert_eq!(data(b"caf\xc3\xa9"), json!({ "text": "café" }));
+        assert_eq!(data(b"caf\xe9"), json!({ "bytes": "Y2Fm6Q==" }));
+    }
+
+    #[test]
+    fn test_line() {
+        assert_eq!(
+            line(true, "a.txt", b"a foo foo\n", 3, 20, &[2..5, 6..9]),
+            json!({
+                "type": "match",
+                "data": {
+                    "path": { "text": "a.txt" },
+                    "lines": { "text": "a foo foo\n" },
+                    "line_number": 3,
+                    "absolute_offset": 20,
+                    "submatches": [
+                        { "match": { "text": "foo" }, "start": 2, "end": 5 },
+                        { "match": { "text": "foo" }, "start": 6, "end": 9 },
+                    ],
+                },
+            })
+        );
+    }
+}
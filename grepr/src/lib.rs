@@ -1,145 +1,1039 @@
-use std::{fs::File, io::{self, BufRead, BufReader}, path::Path, result::Result::Ok};
+mod color;
+mod edit;
+mod json;
+mod pattern;
+mod sink;
+mod walk;
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fs::{self, File},
+    io::{self, BufRead, BufReader, IsTerminal, Read},
+    ops::Deref,
+    path::{Path, PathBuf},
+    result::Result::Ok,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
+    time::Instant,
+};
 
 use anyhow::{Result, anyhow};
-use clap::Parser;
-use regex::{Regex, RegexBuilder};
-use walkdir::WalkDir;
+use clap::{Parser, ValueEnum};
+use color::Colors;
+use globset::{Glob, GlobMatcher};
+use json::Stats;
+use memmap2::Mmap;
+use pattern::{Extent, Matcher, Syntax};
+use sink::{Count, Printer, Sink, Stream, Writer};
+use walk::{Walk, WalkError, find_files};
 
 #[derive(Debug, Parser)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, disable_help_flag = true)]
 pub struct Config {
-    #[arg(value_name = "PATTERN", help = "Search pattern")]
-    pattern: String,
-    #[arg(value_name = "FILE", help = "Input file(s)", default_value = "-")]
-    files: Vec<String>,
+    #[arg(
+        value_name = "PATTERN",
+        help = "Search pattern",
+        required_unless_present_any = ["regexp", "pattern_files"]
+    )]
+    pattern: Option<String>,
+    #[arg(value_name = "FILE", help = "Input file(s) [default: -]")]
+    files: Vec<PathBuf>,
+    #[arg(
+        short = 'e',
+        long,
+        value_name = "PATTERN",
+        help = "Search for PATTERN; may be given more than once"
+    )]
+    regexp: Vec<String>,
+    #[arg(
+        short = 'f',
+        long = "file",
+        value_name = "FILE",
+        help = "Read patterns from FILE, one per line"
+    )]
+    pattern_files: Vec<String>,
+    #[arg(
+        short = 'F',
+        long,
+        group = "syntax",
+        help = "Match the patterns as fixed strings"
+    )]
+    fixed_strings: bool,
+    #[arg(
+        short = 'E',
+        long,
+        group = "syntax",
+        help = "Use the regex crate's syntax (the default)"
+    )]
+    extended_regexp: bool,
+    #[arg(
+        short = 'G',
+        long,
+        group = "syntax",
+        help = "Accepted for compatibility; same as -E"
+    )]
+    basic_regexp: bool,
+    #[arg(
+        short = 'P',
+        long,
+        group = "syntax",
+        help = "Use Perl-like syntax with lookarounds and backreferences"
+    )]
+    perl_regexp: bool,
+    #[arg(short = 'w', long, help = "Match only whole words")]
+    word_regexp: bool,
+    #[arg(short = 'x', long, help = "Match only whole lines")]
+    line_regexp: bool,
+    #[arg(
+        short = 'U',
+        long,
+        help = "Let matches span lines, searching whole files"
+    )]
+    multiline: bool,
+    #[arg(
+        short = 'z',
+        long,
+        help = "Lines end with NUL bytes rather than newlines"
+    )]
+    null_data: bool,
     #[arg(short, long, help = "Recursive search")]
     recursive: bool,
+    #[arg(short = 'R', long, help = "Recursive search, following symbolic links")]
+    dereference_recursive: bool,
+    #[arg(
+        long,
+        value_name = "GLOB",
+        value_parser = parse_glob,
+        help = "Search only files whose name matches GLOB"
+    )]
+    include: Vec<GlobMatcher>,
+    #[arg(
+        long,
+        value_name = "GLOB",
+        value_parser = parse_glob,
+        help = "Skip files whose name matches GLOB"
+    )]
+    exclude: Vec<GlobMatcher>,
+    #[arg(
+        long,
+        value_name = "GLOB",
+        value_parser = parse_glob,
+        help = "Skip directories whose name matches GLOB when recursing"
+    )]
+    exclude_dir: Vec<GlobMatcher>,
+    #[arg(
+        long,
+        help = "Skip files ignored by .ignore files, and by .gitignore files in git repositories"
+    )]
+    respect_ignore: bool,
+    #[arg(
+        short = 'j',
+        long,
+        value_name = "N",
+        default_value_t = 0,
+        help = "Search with N threads (0 for one per CPU)"
+    )]
+    threads: usize,
     #[arg(short, long, help = "Count occurrences")]
     count: bool,
     #[arg(short = 'v', long, help = "Invert match")]
     invert_match: bool,
     #[arg(short, long, help = "Case-insensitive")]
     insensitive: bool,
+    #[arg(short = 'n', long, help = "Prefix each line with its line number")]
+    line_number: bool,
+    #[arg(
+        short = 'b',
+        long,
+        help = "Prefix each line with its byte offset in the file"
+    )]
+    byte_offset: bool,
+    #[arg(
+        short = 'H',
+        long,
+        overrides_with = "no_filename",
+        help = "Prefix each line with the file name"
+    )]
+    with_filename: bool,
+    #[arg(
+        short = 'h',
+        long,
+        overrides_with = "with_filename",
+        help = "Never prefix lines with the file name"
+    )]
+    no_filename: bool,
+    #[arg(short = 'l', long, help = "Print only the names of files with matches")]
+    files_with_matches: bool,
+    #[arg(
+        short = 'L',
+        long,
+        conflicts_with = "files_with_matches",
+        help = "Print only the names of files without matches"
+    )]
+    files_without_match: bool,
+    #[arg(
+        long,
+        value_name = "LABEL",
+        default_value = "(standard input)",
+        help = "Name to show for standard input"
+    )]
+    label: String,
+    #[arg(
+        short = 'A',
+        long,
+        value_name = "NUM",
+        help = "Print NUM lines of context after each match"
+    )]
+    after_context: Option<usize>,
+    #[arg(
+        short = 'B',
+        long,
+        value_name = "NUM",
+        help = "Print NUM lines of context before each match"
+    )]
+    before_context: Option<usize>,
+    #[arg(
+        short = 'C',
+        long,
+        value_name = "NUM",
+        help = "Print NUM lines of context around each match"
+    )]
+    context: Option<usize>,
+    #[arg(
+        short = 'o',
+        long,
+        help = "Print only the matched parts, each on its own line"
+    )]
+    only_matching: bool,
+    #[arg(
+        short = 'm',
+        long,
+        value_name = "NUM",
+        help = "Stop reading a file after NUM matching lines"
+    )]
+    max_count: Option<usize>,
+    #[arg(
+        long,
+        alias = "colour",
+        value_name = "WHEN",
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_value_t = ColorChoice::Auto,
+        default_missing_value = "auto",
+        help = "Highlight matches, file names and line numbers (see GREP_COLORS)"
+    )]
+    color: ColorChoice,
+    #[arg(
+        short = 'q',
+        long,
+        alias = "silent",
+        help = "Print nothing; exit with status 1 unless a line is selected"
+    )]
+    quiet: bool,
+    #[arg(short = 'a', long, help = "Search binary files as if they were text")]
+    text: bool,
+    #[arg(
+        long,
+        value_name = "TYPE",
+        value_enum,
+        default_value_t = BinaryFiles::Binary,
+        help = "How to treat files with NUL bytes"
+    )]
+    binary_files: BinaryFiles,
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        help = "Print matches replaced by TEMPLATE, where $1 or ${name} is a group"
+    )]
+    replace: Option<String>,
+    #[arg(
+        long,
+        requires = "replace",
+        conflicts_with_all = ["invert_match", "multiline", "null_data"],
+        help = "Write the replacements to the files"
+    )]
+    in_place: bool,
+    #[arg(
+        long,
+        value_name = "SUFFIX",
+        requires = "in_place",
+        help = "Keep the original files with SUFFIX added to their names"
+    )]
+    backup: Option<String>,
+    #[arg(
+        long,
+        requires = "in_place",
+        help = "Print the changes as a diff instead of writing them"
+    )]
+    dry_run: bool,
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "count",
+            "files_with_matches",
+            "files_without_match",
+            "only_matching",
+            "quiet",
+            "replace",
+        ],
+        help = "Print the results as JSON lines, in the format of ripgrep"
+    )]
+    json: bool,
+    #[arg(long, action = clap::ArgAction::Help, help = "Print help")]
+    help: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ColorChoice {
+    // Only when writing to a terminal.
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum BinaryFiles {
+    // Print a message instead of the matching lines.
+    Binary,
+    Text,
+    // Assume that binary files do not match.
+    WithoutMatch,
+}
+
+/// A line selected by `find_lines`, either a match or context around one.
+#[derive(Debug, PartialEq)]
+struct Line {
+    is_match: bool,
+    /// Starting from 1.
+    line_number: usize,
+    /// Offset of the start of the line from the start of the input.
+    byte_offset: usize,
+    /// The line including its terminator, if any, as bytes that need not be
+    /// valid UTF-8.
+    text: Vec<u8>,
+}
+
+/// The result of searching one input, which is written once the inputs
+/// before it are written. Output sent by `Printer` as it was found is not
+/// included.
+#[derive(Debug, Default)]
+struct Report {
+    output: Vec<u8>,
+    /// Set when the output has matching or context lines.
+    lines: bool,
+    /// Printed on stderr after the output.
+    error: Option<String>,
+    selected: bool,
+    failed: bool,
+    stats: Stats,
+}
+
+/// What the threads searching the inputs send to the one writing the output.
+#[derive(Debug)]
+enum Message {
+    /// Output of the input being written, sent before its search is done.
+    Output(Vec<u8>),
+    /// The rest of the output of an input, with its index.
+    Done(usize, Report),
+}
+
+/// Why `find_lines` failed. A read error only ends the search of one input,
+/// while failing to write the output ends the whole search.
+#[derive(Debug)]
+enum SearchError {
+    Read(io::Error),
+    Write(io::Error),
+}
+
+/// Which lines `find_lines` and `find_regions` select.
+#[derive(Debug, Clone, Copy)]
+struct Selection<'a> {
+    pattern: &'a Matcher,
+    invert_match: bool,
+    /// Lines of context before and after the selected lines.
+    before: usize,
+    after: usize,
+    max_count: Option<usize>,
+    /// Ends lines: a newline, or NUL with -z.
+    terminator: u8,
+}
+
+/// An input opened for searching.
+enum Input {
+    Lines(Box<dyn BufRead>),
+    /// The whole input, for -U.
+    Buffer(Buffer),
+}
+
+/// The contents of an input, mapped into memory if it is a large file.
+enum Buffer {
+    Mapped(Mmap),
+    Read(Vec<u8>),
+}
+
+impl Deref for Buffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Buffer::Mapped(map) => map,
+            Buffer::Read(data) => data,
+        }
+    }
+}
+
+fn parse_glob(s: &str) -> Result<GlobMatcher, String> {
+    Glob::new(s)
+        .map(|g| g.compile_matcher())
+        .map_err(|e| e.to_string())
 }
 
 pub fn get_args() -> Result<Config> {
-    let config = Config::parse();
-    // println!("{:#?}", config);
+    let mut config = Config::parse();
+    // With -e or -f, the first argument is a file rather than the pattern.
+    if (!config.regexp.is_empty() || !config.pattern_files.is_empty())
+        && let Some(file) = config.pattern.take()
+    {
+        config.files.insert(0, file.into());
+    }
+    if config.files.is_empty() {
+        config.files.push("-".into());
+    }
     Ok(config)
 }
 
-pub fn run(config: Config) -> Result<()> {
-    let pat = RegexBuilder::new(&config.pattern)
-        .case_insensitive(config.insensitive)
-        .build()
-        .map_err(|e| anyhow!("Invalid pattern \"{}\": {}", config.pattern, e))?;
-    for f in find_files(&config.files, config.recursive) {
-        let f = match f {
-            Ok(f) => f,
-            Err(e) => {
-                eprintln!("{e}");
-                continue;
-            },
-        };
+// Gathers the patterns in the order GNU grep does: -e, then -f, then the
+// first argument.
+fn patterns(config: &Config) -> Result<Vec<String>> {
+    let mut patterns = config.regexp.clone();
+    for file in &config.pattern_files {
+        let text = fs::read_to_string(file).map_err(|e| anyhow!("{}: {}", file, io_message(&e)))?;
+        patterns.extend(text.lines().map(String::from));
+    }
+    patterns.extend(config.pattern.clone());
+    Ok(patterns)
+}
 
-        let mut buf = open(&f).unwrap();
-        let results = find_lines(&mut buf, &pat, config.invert_match);
+/// Returns the exit status, as GNU grep does: with `-q`, 0 when a line was
+/// selected; otherwise 2 when an input could not be read; 1 with `-q` when
+/// no line was selected; 0 otherwise.
+pub fn run(config: Config) -> Result<i32> {
+    let syntax = if config.fixed_strings {
+        Syntax::Fixed
+    } else if config.perl_regexp {
+        Syntax::Pcre
+    } else {
+        Syntax::Regex
+    };
+    // As in GNU grep, -x takes precedence over -w.
+    let extent = if config.line_regexp {
+        Extent::Line
+    } else if config.word_regexp {
+        Extent::Word
+    } else {
+        Extent::Anywhere
+    };
+    let pat = Matcher::new(
+        &patterns(&config)?,
+        syntax,
+        extent,
+        config.insensitive,
+        config.multiline,
+    )
+    .map_err(|e| anyhow!(e))?;
+    let show_filename = config.with_filename
+        || (!config.no_filename
+            && (config.files.len() > 1 || config.recursive || config.dereference_recursive));
+    // Context is not printed with -o.
+    let (before, after) = if config.only_matching {
+        (0, 0)
+    } else {
+        (
+            config.before_context.or(config.context).unwrap_or(0),
+            config.after_context.or(config.context).unwrap_or(0),
+        )
+    };
+    let colors = match config.color {
+        ColorChoice::Always => Colors::from_env(),
+        ColorChoice::Auto if io::stdout().is_terminal() => Colors::from_env(),
+        _ => Colors::default(),
+    };
+    let selection = Selection {
+        pattern: &pat,
+        invert_match: config.invert_match,
+        before,
+        after,
+        max_count: config.max_count,
+        terminator: if config.null_data { b'\0' } else { b'\n' },
+    };
+    // JSON messages need no separators between groups of lines.
+    let printer = Printer::new(
+        &config,
+        &pat,
+        &colors,
+        show_filename,
+        (before > 0 || after > 0) && !config.json,
+    );
 
-        match results {
-            Ok(results) => {
-                let mut prefix = String::from("");
-                if config.files.len() > 1 || config.recursive {
-                    prefix = format!("{f}:");
-                }
-                if config.count {
-                    println!("{}{}", prefix, results.len());
-                } else {
-                    for result in results {
-                        print!("{prefix}{result}");
+    match search(&config, &selection, &printer) {
+        // The reader of the output, e.g. `head`, has seen enough.
+        Err(e)
+            if e.downcast_ref::<io::Error>()
+                .is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe) =>
+        {
+            Ok(0)
+        }
+        result => result,
+    }
+}
+
+// How much output of the input being written is gathered before it is sent
+// to the main thread, unless stdout is a terminal.
+const CHUNK: usize = 64 * 1024;
+
+// Searches the inputs with several threads, while the main thread writes the
+// results in the order of the inputs. The output of the input that is next
+// in order is written as it is found, while the others are kept until their
+// turn. With -q, stops at the first selected line. Inputs that cannot be
+// read are reported and skipped.
+fn search(config: &Config, selection: &Selection, printer: &Printer) -> Result<i32> {
+    let threads = match config.threads {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    };
+    let files = find_files(&config.files, &Walk::from(config), threads);
+    // The index of the next input for a thread to search.
+    let claimed = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let next = AtomicUsize::new(0);
+    // Bounded, so that a thread cannot get far ahead of the output.
+    let (tx, rx) = mpsc::sync_channel(threads * 4);
+    let chunk = if io::stdout().is_terminal() { 1 } else { CHUNK };
+    let started = Instant::now();
+    let context = (selection.before > 0 || selection.after > 0) && !config.json;
+    let mut writer = Writer::new(printer.colors(), context);
+
+    thread::scope(|s| {
+        for _ in 0..threads {
+            let tx = tx.clone();
+            let mut printer = printer.clone();
+            let (files, claimed, stop, next) = (&files, &claimed, &stop, &next);
+            s.spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let index = claimed.fetch_add(1, Ordering::Relaxed);
+                    let Some(file) = files.get(index) else {
+                        break;
+                    };
+                    printer.stream_to(Stream {
+                        tx: tx.clone(),
+                        next,
+                        index,
+                        chunk,
+                    });
+                    let report = search_input(config, selection, file, &mut printer);
+                    if config.quiet && report.selected {
+                        stop.store(true, Ordering::Relaxed);
+                    }
+                    if tx.send(Message::Done(index, report)).is_err() {
+                        break;
                     }
                 }
-            },
-            Err(e) => return Err(e),
+            });
         }
-    }
-    Ok(())
+        drop(tx);
+        let result = write_reports(rx, &next, &mut writer, config, started);
+        stop.store(true, Ordering::Relaxed);
+        result
+    })
 }
 
-fn open(filename: &str) -> Result<Box<dyn BufRead>> {
-    match filename {
-        "-" => Ok(Box::new(BufReader::new(io::stdin()))),
-        _ => Ok(Box::new(BufReader::new(File::open(filename)?))),
+// Writes the output of the threads in the order of the inputs, followed by
+// the summary with --json. `next` is the index of the input being written,
+// whose output is written as it comes. The reports of the inputs after it
+// wait until their turn.
+fn write_reports(
+    rx: mpsc::Receiver<Message>,
+    next: &AtomicUsize,
+    writer: &mut Writer,
+    config: &Config,
+    started: Instant,
+) -> Result<i32> {
+    let quiet = config.quiet;
+    let mut pending = BTreeMap::new();
+    // Whether output of the input being written was written already.
+    let mut streamed = false;
+    let (mut selected, mut failed) = (false, false);
+    let mut stats = Stats::default();
+    for message in rx {
+        let (index, report) = match message {
+            Message::Output(output) => {
+                writer.write(&output, !streamed)?;
+                streamed = true;
+                continue;
+            }
+            Message::Done(index, report) => (index, report),
+        };
+        pending.insert(index, report);
+        while let Some(report) = pending.remove(&next.load(Ordering::Relaxed)) {
+            writer.write(&report.output, report.lines && !streamed)?;
+            streamed = false;
+            next.fetch_add(1, Ordering::Relaxed);
+            if let Some(e) = &report.error {
+                writer.flush()?;
+                eprintln!("grepr: {e}");
+            }
+            selected |= report.selected;
+            failed |= report.failed;
+            stats.add(&report.stats);
+            if quiet && selected {
+                return Ok(0);
+            }
+        }
+    }
+    if config.json {
+        let mut summary = serde_json::to_vec(&json::summary(started.elapsed(), stats))?;
+        summary.push(b'\n');
+        writer.write(&summary, false)?;
     }
+    writer.flush()?;
+    Ok(if quiet && selected {
+        0
+    } else if failed {
+        2
+    } else if quiet {
+        1
+    } else {
+        0
+    })
 }
 
-fn find_files(paths: &[String], recursive: bool) -> Vec<Result<String>> {
-    let mut results: Vec<Result<String>> = Vec::new();
-
-    if paths.len() == 1 && paths[0].eq("-") {
-        results.push(Ok(paths[0].clone()));
-        return results;
+// Searches one input found by `find_files`.
+fn search_input(
+    config: &Config,
+    selection: &Selection,
+    file: &Result<PathBuf, WalkError>,
+    printer: &mut Printer,
+) -> Report {
+    let f = match file {
+        Ok(f) => f,
+        Err(e) => {
+            return Report {
+                failed: !e.warning,
+                error: Some(e.to_string()),
+                ..Report::default()
+            };
+        }
+    };
+    // Names that are not valid UTF-8 are only changed for display.
+    let name = if f.as_os_str() == "-" {
+        config.label.clone()
+    } else {
+        f.display().to_string()
+    };
+    if let Some(template) = config.replace.as_ref().filter(|_| config.in_place) {
+        return edit_input(config, selection.pattern, template, f);
     }
+    let mut input = match open(f, config.multiline) {
+        Ok(input) => input,
+        Err(e) => {
+            return Report {
+                error: Some(format!("{name}: {}", io_message(&e))),
+                failed: true,
+                ..Report::default()
+            };
+        }
+    };
+    printer.start(&name);
 
-    for path in paths {
-        if !recursive && Path::new(path).is_dir() {
-            results.push(Err(anyhow!(
-                "{} is a directory",
-                path
-            )));
-            continue;
+    let result = search_file(config, selection, &mut input, printer).and_then(|selected| {
+        let stats = printer.finish().map_err(SearchError::Write)?;
+        Ok((selected, stats))
+    });
+    let mut report = Report {
+        output: printer.take(),
+        lines: printer.printed_lines(),
+        ..Report::default()
+    };
+    match result {
+        Ok((selected, stats)) => {
+            report.selected = selected;
+            report.stats = stats;
+        }
+        Err(SearchError::Read(e) | SearchError::Write(e)) => {
+            report.error = Some(format!("{name}: {}", io_message(&e)));
+            report.failed = true;
         }
+    }
+    report
+}
 
-        for entry in WalkDir::new(path) {
-            let dir_entry = match entry {
-                Ok(dir_entry) => dir_entry,
-                Err(e) => {
-                    results.push(Err(anyhow!(e)));
-                    continue;
-                }
-            };
+// Makes the replacements in one file for --in-place.
+fn edit_input(config: &Config, pat: &Matcher, template: &str, path: &Path) -> Report {
+    if path.as_os_str() == "-" {
+        return Report {
+            error: Some(format!("{}: cannot edit standard input", config.label)),
+            failed: true,
+            ..Report::default()
+        };
+    }
+    match edit::edit(config, pat, template, path) {
+        Ok(edit) => Report {
+            output: edit.diff,
+            selected: edit.selected,
+            ..Report::default()
+        },
+        Err(e) => Report {
+            error: Some(format!("{}: {}", path.display(), io_message(&e))),
+            failed: true,
+            ..Report::default()
+        },
+    }
+}
 
-            let meta = match dir_entry.metadata() {
-                Ok(meta) => meta,
-                Err(e) => {
-                    results.push(Err(anyhow!(e)));
-                    continue;
-                }
-            };
+// Searches one input, returning whether a line was selected.
+fn search_file(
+    config: &Config,
+    selection: &Selection,
+    input: &mut Input,
+    printer: &mut Printer,
+) -> Result<bool, SearchError> {
+    let binary_files = if config.text {
+        BinaryFiles::Text
+    } else {
+        config.binary_files
+    };
+    // With -z, NUL bytes are line terminators rather than a sign of binary
+    // data.
+    let binary = binary_files != BinaryFiles::Text
+        && !config.null_data
+        && input.is_binary().map_err(SearchError::Read)?;
+    let skip = binary && binary_files == BinaryFiles::WithoutMatch;
 
-            if !meta.is_dir() {
-                results.push(Ok(format!("{}", dir_entry.path().display())));
+    if config.quiet
+        || config.files_with_matches
+        || config.files_without_match
+        || config.count
+        || binary
+    {
+        // Only -c needs more than the first match.
+        let mut count = Count {
+            count: 0,
+            limit: if config.count && !config.quiet {
+                None
+            } else {
+                Some(1)
+            },
+        };
+        if !skip {
+            let selection = Selection {
+                before: 0,
+                after: 0,
+                ..*selection
+            };
+            let bytes = input.search(&selection, &mut count)?;
+            printer.searched(bytes);
+        }
+        let result = if config.quiet {
+            Ok(())
+        } else if config.count {
+            printer.count(count.count)
+        } else if config.files_with_matches || config.files_without_match {
+            if (count.count == 0) == config.files_without_match {
+                printer.name()
+            } else {
+                Ok(())
             }
+        } else if count.count > 0 {
+            printer.binary_matches()
+        } else {
+            Ok(())
+        };
+        result.map_err(SearchError::Write)?;
+        return Ok(count.count > 0);
+    }
+
+    let bytes = input.search(selection, printer)?;
+    printer.searched(bytes);
+    Ok(printer.selected())
+}
+
+// Like GNU grep, treats an input with a NUL byte in its first block as
+// binary.
+fn is_binary(buf: &mut impl BufRead) -> io::Result<bool> {
+    let block = buf.fill_buf()?;
+    Ok(block[..block.len().min(BLOCK)].contains(&0))
+}
+
+// The size of the blocks read from inputs, as with `BufReader`.
+const BLOCK: usize = 8 * 1024;
+
+// Files at least this large are mapped into memory for -U rather than read.
+const MMAP_MIN: u64 = 1024 * 1024;
+
+/// Describes an I/O error without the `(os error N)` suffix of its `Display`
+/// output, e.g. `No such file or directory`.
+pub(crate) fn io_message(e: &io::Error) -> String {
+    let message = e.to_string();
+    match message.find(" (os error ") {
+        Some(i) => message[..i].to_string(),
+        None => message,
+    }
+}
+
+// Opens an input to be read line by line, or with `whole` read at once.
+fn open(filename: &Path, whole: bool) -> io::Result<Input> {
+    let stdin = filename.as_os_str() == "-";
+    if !whole {
+        return Ok(Input::Lines(match stdin {
+            true => Box::new(BufReader::new(io::stdin())),
+            false => Box::new(BufReader::new(File::open(filename)?)),
+        }));
+    }
+    let mut data = Vec::new();
+    if stdin {
+        io::stdin().read_to_end(&mut data)?;
+        return Ok(Input::Buffer(Buffer::Read(data)));
+    }
+    let mut file = File::open(filename)?;
+    let metadata = file.metadata()?;
+    if metadata.is_file() && metadata.len() >= MMAP_MIN {
+        // SAFETY: the map is only read, and grepr does not write to the
+        // files it searches. If another process truncates the file
+        // meanwhile, the search may fail with SIGBUS, as with ripgrep.
+        let map = unsafe { Mmap::map(&file)? };
+        return Ok(Input::Buffer(Buffer::Mapped(map)));
+    }
+    file.read_to_end(&mut data)?;
+    Ok(Input::Buffer(Buffer::Read(data)))
+}
+
+impl Input {
+    fn is_binary(&mut self) -> io::Result<bool> {
+        match self {
+            Input::Lines(buf) => is_binary(buf),
+            Input::Buffer(data) => is_binary(&mut &data[..]),
+        }
+    }
+
+    // Returns the number of bytes searched.
+    fn search(&mut self, selection: &Selection, sink: &mut impl Sink) -> Result<u64, SearchError> {
+        match self {
+            Input::Lines(buf) => find_lines(buf, selection, sink),
+            Input::Buffer(data) => find_regions(data, selection, sink),
         }
     }
-    results
 }
 
+/// Reads `file` line by line, passing each selected line to `sink` as soon
+/// as it is known: the lines that match the pattern (or do not, with
+/// `invert_match`), and up to `before` and `after` lines of context around
+/// them. Only the last `before` lines are kept in memory. After
+/// `max_count` matches, only the context that follows the last one is
+/// read. Stops early if `sink` asks to. Returns the number of bytes read.
 fn find_lines<T: BufRead>(
     mut file: T,
-    pattern: &Regex,
-    invert_match: bool,
-) -> Result<Vec<String>> {
-    let mut lines = Vec::new();
-    loop {
-        let mut line = String::new();
-        let bytes = file.read_line(&mut line)?;
+    selection: &Selection,
+    sink: &mut impl Sink,
+) -> Result<u64, SearchError> {
+    let terminator = selection.terminator;
+    let mut selector = Selector::new(selection, sink);
+    let mut byte_offset = 0;
+    for line_number in 1.. {
+        if selector.finished() {
+            break;
+        }
+        let mut text = Vec::new();
+        let bytes = file
+            .read_until(terminator, &mut text)
+            .map_err(SearchError::Read)?;
         if bytes == 0 {
             break;
         }
 
-        if pattern.is_match(&line) ^ invert_match {
-            lines.push(line);
+        // The terminator is not part of what is matched, so that `$`
+        // matches at the end of the line.
+        let content = text.strip_suffix(&[terminator]).unwrap_or(&text);
+        let is_match = selection.pattern.is_match(content) ^ selection.invert_match;
+        let line = Line {
+            is_match: is_match && !selector.limit_reached(),
+            line_number,
+            byte_offset,
+            text,
+        };
+        byte_offset += bytes;
+        if !selector.push(line)? {
+            break;
+        }
+    }
+    Ok(byte_offset as u64)
+}
+
+/// Runs the pattern over the whole of `data` for -U, so that a match can
+/// span lines. The lines that a match overlaps are passed to `sink` as a
+/// single `Line`, numbered after the first of them, while the other lines
+/// are passed on their own, as with `find_lines`. Returns the number of
+/// bytes searched, which is all of them.
+fn find_regions(
+    data: &[u8],
+    selection: &Selection,
+    sink: &mut impl Sink,
+) -> Result<u64, SearchError> {
+    let terminator = selection.terminator;
+    // The offset after the end of the line that `offset` is in.
+    let line_end = |offset: usize| {
+        data[offset..]
+            .iter()
+            .position(|&b| b == terminator)
+            .map_or(data.len(), |i| offset + i + 1)
+    };
+    let mut selector = Selector::new(selection, sink);
+    let mut matches = selection.pattern.find_iter(data).peekable();
+    let mut byte_offset = 0;
+    let mut line_number = 1;
+    while byte_offset < data.len() && !selector.finished() {
+        let mut end = line_end(byte_offset);
+        let mut found = false;
+        while let Some(m) = matches.next_if(|m| m.start < end) {
+            found = true;
+            if m.end > end {
+                end = line_end(m.end - 1);
+            }
+        }
+
+        let text = data[byte_offset..end].to_vec();
+        let lines = count_lines(&text, terminator);
+        let line = Line {
+            is_match: (found ^ selection.invert_match) && !selector.limit_reached(),
+            line_number,
+            byte_offset,
+            text,
+        };
+        byte_offset = end;
+        line_number += lines;
+        if !selector.push(line)? {
+            break;
+        }
+    }
+    Ok(data.len() as u64)
+}
+
+/// Counts the lines in `text`, including a last line without a terminator.
+fn count_lines(text: &[u8], terminator: u8) -> usize {
+    text.split_inclusive(|&b| b == terminator).count()
+}
+
+// Passes the selected lines to a sink, along with the context around them.
+struct Selector<'a, S> {
+    before: usize,
+    after: usize,
+    max_count: Option<usize>,
+    sink: &'a mut S,
+    /// The last lines that were not selected, which may become context.
+    before_lines: VecDeque<Line>,
+    /// How many lines of context are still to follow the last selected line.
+    after_left: usize,
+    matches: usize,
+}
+
+impl<'a, S: Sink> Selector<'a, S> {
+    fn new(selection: &Selection, sink: &'a mut S) -> Self {
+        Selector {
+            before: selection.before,
+            after: selection.after,
+            max_count: selection.max_count,
+            sink,
+            before_lines: VecDeque::with_capacity(selection.before),
+            after_left: 0,
+            matches: 0,
+        }
+    }
+
+    // Like GNU grep, lines after the last match allowed by -m are context
+    // even if they match.
+    fn limit_reached(&self) -> bool {
+        self.max_count.is_some_and(|max| self.matches >= max)
+    }
+
+    // Whether no more lines can be passed on.
+    fn finished(&self) -> bool {
+        self.limit_reached() && self.after_left == 0
+    }
+
+    // Handles the next line, returning false if the sink asks to stop.
+    fn push(&mut self, line: Line) -> Result<bool, SearchError> {
+        if line.is_match {
+            self.matches += 1;
+            self.after_left = self.after;
+            for context in self.before_lines.drain(..) {
+                if !self.sink.line(context).map_err(SearchError::Write)? {
+                    return Ok(false);
+                }
+            }
+            self.sink.line(line).map_err(SearchError::Write)
+        } else if self.after_left > 0 {
+            self.after_left -= 1;
+            self.sink.line(line).map_err(SearchError::Write)
+        } else {
+            if self.before > 0 {
+                if self.before_lines.len() == self.before {
+                    self.before_lines.pop_front();
+                }
+                self.before_lines.push_back(line);
+            }
+            Ok(true)
         }
     }
-    Ok(lines)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{find_files, find_lines};
-    use rand::{distributions::Alphanumeric, Rng};
-    use regex::{Regex, RegexBuilder};
-    use std::io::Cursor;
+    use super::{
+        Count, Line, Matcher, SearchError, Selection, Sink, find_lines, find_regions, is_binary,
+    };
+    use regex::bytes::{Regex, RegexBuilder};
+    use std::io::{self, Cursor, Read};
+
+    impl Sink for Vec<Line> {
+        fn line(&mut self, line: Line) -> io::Result<bool> {
+            self.push(line);
+            Ok(true)
+        }
+    }
+
+    fn selection(pattern: &Matcher) -> Selection<'_> {
+        Selection {
+            pattern,
+            invert_match: false,
+            before: 0,
+            after: 0,
+            max_count: None,
+            terminator: b'\n',
+        }
+    }
+
+    // Collects the lines selected by `find_lines`.
+    fn collect(
+        text: &[u8],
+        pattern: &Regex,
+        invert_match: bool,
+        before: usize,
+        after: usize,
+    ) -> Vec<Line> {
+        let pattern = Matcher::Rust(pattern.clone());
+        let selection = Selection {
+            invert_match,
+            before,
+            after,
+            ..selection(&pattern)
+        };
+        let mut lines = Vec::new();
+        find_lines(Cursor::new(text), &selection, &mut lines).unwrap();
+        lines
+    }
 
     #[test]
     fn test_find_lines() {
@@ -147,14 +1041,12 @@ mod tests {
 
         // The pattern _or_ should match the one line, "Lorem"
         let re1 = Regex::new("or").unwrap();
-        let matches = find_lines(Cursor::new(&text), &re1, false);
-        assert!(matches.is_ok());
-        assert_eq!(matches.unwrap().len(), 1);
+        let matches = collect(text, &re1, false, 0, 0);
+        assert_eq!(matches.len(), 1);
 
         // When inverted, the function should match the other two lines
-        let matches = find_lines(Cursor::new(&text), &re1, true);
-        assert!(matches.is_ok());
-        assert_eq!(matches.unwrap().len(), 2);
+        let matches = collect(text, &re1, true, 0, 0);
+        assert_eq!(matches.len(), 2);
 
         // This regex will be case-insensitive
         let re2 = RegexBuilder::new("or")
@@ -163,58 +1055,217 @@ mod tests {
             .unwrap();
 
         // The two lines "Lorem" and "DOLOR" should match
-        let matches = find_lines(Cursor::new(&text), &re2, false);
-        assert!(matches.is_ok());
-        assert_eq!(matches.unwrap().len(), 2);
+        let matches = collect(text, &re2, false, 0, 0);
+        assert_eq!(matches.len(), 2);
 
         // When inverted, the one remaining line should match
-        let matches = find_lines(Cursor::new(&text), &re2, true);
-        assert!(matches.is_ok());
-        assert_eq!(matches.unwrap().len(), 1);
+        let matches = collect(text, &re2, true, 0, 0);
+        assert_eq!(matches.len(), 1);
     }
 
     #[test]
-    fn test_find_files() {
-        // Verify that the function finds a file known to exist
-        let files = find_files(&["./tests/inputs/fox.txt".to_string()], false);
-        assert_eq!(files.len(), 1);
-        assert_eq!(files[0].as_ref().unwrap(), "./tests/inputs/fox.txt");
-
-        // The function should reject a directory without the recursive option
-        let files = find_files(&["./tests/inputs".to_string()], false);
-        assert_eq!(files.len(), 1);
-        if let Err(e) = &files[0] {
-            assert_eq!(e.to_string(), "./tests/inputs is a directory");
-        }
-
-        // Verify the function recurses to find four files in the directory
-        let res = find_files(&["./tests/inputs".to_string()], true);
-        let mut files: Vec<String> = res
-            .iter()
-            .map(|r| r.as_ref().unwrap().replace('\\', "/"))
-            .collect();
-        files.sort();
-        assert_eq!(files.len(), 4);
+    fn test_find_lines_positions() {
+        let text = b"Lorem\nIpsum\r\nDOLOR";
+
+        // Line numbers start at 1 and offsets count the line terminators
+        let re = RegexBuilder::new("m|r$").build().unwrap();
+        let matches = collect(text, &re, false, 0, 0);
         assert_eq!(
-            files,
+            matches,
             vec![
-                "./tests/inputs/bustle.txt",
-                "./tests/inputs/empty.txt",
-                "./tests/inputs/fox.txt",
-                "./tests/inputs/nobody.txt",
+                Line {
+                    is_match: true,
+                    line_number: 1,
+                    byte_offset: 0,
+                    text: b"Lorem\n".to_vec(),
+                },
+                Line {
+                    is_match: true,
+                    line_number: 2,
+                    byte_offset: 6,
+                    text: b"Ipsum\r\n".to_vec(),
+                },
             ]
         );
 
-        // Generate a random string to represent a nonexistent file
-        let bad: String = rand::thread_rng()
-            .sample_iter(&Alphanumeric)
-            .take(7)
-            .map(char::from)
-            .collect();
-
-        // Verify that the function returns the bad file as an error
-        let files = find_files(&[bad], false);
-        assert_eq!(files.len(), 1);
-        assert!(files[0].is_err());
+        let matches = collect(text, &re, true, 0, 0);
+        assert_eq!(
+            matches,
+            vec![Line {
+                is_match: true,
+                line_number: 3,
+                byte_offset: 13,
+                text: b"DOLOR".to_vec(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_find_lines_context() {
+        let text = b"1 x\n2\n3\n4\n5 x\n6\n7\n8\n9\n10 x\n11\n";
+        let re = Regex::new("x").unwrap();
+        let numbers = |lines: Vec<Line>| -> Vec<(usize, bool)> {
+            lines.iter().map(|l| (l.line_number, l.is_match)).collect()
+        };
+
+        // Context lines are only emitted once and never past the input
+        assert_eq!(
+            numbers(collect(text, &re, false, 1, 2)),
+            [
+                (1, true),
+                (2, false),
+                (3, false),
+                (4, false),
+                (5, true),
+                (6, false),
+                (7, false),
+                (9, false),
+                (10, true),
+                (11, false),
+            ]
+        );
+
+        // Before-context only reaches back to the previous match
+        assert_eq!(
+            numbers(collect(text, &re, false, 5, 0)),
+            [
+                (1, true),
+                (2, false),
+                (3, false),
+                (4, false),
+                (5, true),
+                (6, false),
+                (7, false),
+                (8, false),
+                (9, false),
+                (10, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_lines_max_count() {
+        let text = b"a x\nb x\nc\nd x\ne\n";
+        let re = Matcher::Rust(Regex::new("x").unwrap());
+        let find = |max_count, after| {
+            let selection = Selection {
+                after,
+                max_count: Some(max_count),
+                ..selection(&re)
+            };
+            let mut lines = Vec::new();
+            find_lines(Cursor::new(text), &selection, &mut lines).unwrap();
+            lines
+                .iter()
+                .map(|l| (l.line_number, l.is_match))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(find(2, 0), [(1, true), (2, true)]);
+        assert_eq!(find(0, 1), []);
+
+        // Lines after the last match are context, whether they match or not
+        assert_eq!(find(1, 2), [(1, true), (2, false), (3, false)]);
+    }
+
+    #[test]
+    fn test_find_lines_invalid_utf8() {
+        // Lines are matched and kept as bytes
+        let text = b"a\xff x\n\xfe\n";
+        let lines = collect(text, &Regex::new("x").unwrap(), false, 0, 0);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].text, b"a\xff x\n");
+    }
+
+    // A reader that fails once the data before it is read.
+    struct Failing;
+
+    impl Read for Failing {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("read failed"))
+        }
+    }
+
+    #[test]
+    fn test_find_lines_stops() {
+        let re = Matcher::Rust(Regex::new("x").unwrap());
+        let reader = || io::BufReader::new(Cursor::new(b"a x\n".to_vec()).chain(Failing));
+
+        // Stopping at the first match never reads past it
+        let mut count = Count {
+            count: 0,
+            limit: Some(1),
+        };
+        assert!(find_lines(reader(), &selection(&re), &mut count).is_ok());
+        assert_eq!(count.count, 1);
+
+        let mut count = Count::default();
+        let result = find_lines(reader(), &selection(&re), &mut count);
+        assert!(matches!(result, Err(SearchError::Read(_))));
+    }
+
+    #[test]
+    fn test_find_lines_null_data() {
+        let re = Matcher::Rust(Regex::new("^b").unwrap());
+        let selection = Selection {
+            terminator: b'\0',
+            ..selection(&re)
+        };
+        let mut lines = Vec::new();
+        find_lines(Cursor::new(b"a\nb\0b\nc\0"), &selection, &mut lines).unwrap();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].text, b"b\nc\0");
+        assert_eq!(lines[0].byte_offset, 4);
+    }
+
+    #[test]
+    fn test_find_regions() {
+        let text = b"fn a(&self) {}\nfn b(\n    self,\n) {}\nx\nfn c(\n    self\n)";
+        let re = Matcher::Rust(Regex::new(r"(?m)fn \w+\(\n\s+self|^x$").unwrap());
+        let find = |before, max_count| {
+            let selection = Selection {
+                before,
+                max_count,
+                ..selection(&re)
+            };
+            let mut lines = Vec::new();
+            find_regions(text, &selection, &mut lines).unwrap();
+            lines
+                .into_iter()
+                .map(|l| {
+                    (
+                        l.line_number,
+                        l.is_match,
+                        String::from_utf8(l.text).unwrap(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Each match is printed whole, from the start of its first line
+        assert_eq!(
+            find(0, None),
+            [
+                (2, true, "fn b(\n    self,\n".to_string()),
+                (5, true, "x\n".to_string()),
+                (6, true, "fn c(\n    self\n".to_string()),
+            ]
+        );
+
+        // Context and -m count regions rather than lines
+        assert_eq!(
+            find(1, Some(1)),
+            [
+                (1, false, "fn a(&self) {}\n".to_string()),
+                (2, true, "fn b(\n    self,\n".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_is_binary() {
+        assert!(is_binary(&mut Cursor::new(b"text\0more\n")).unwrap());
+        assert!(!is_binary(&mut Cursor::new(b"text\xff\n")).unwrap());
+        assert!(!is_binary(&mut Cursor::new(b"")).unwrap());
     }
 }
@@ -0,0 +1,273 @@
+use std::ops::Range;
+
+use regex::bytes::RegexBuilder;
+
+/// How the patterns given with `-e`, `-f` or as the first argument are
+/// interpreted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    /// The `regex` crate's syntax, used for `-E` and `-G` as well.
+    Regex,
+    /// `-F`: the patterns are literal strings.
+    Fixed,
+    /// `-P`: Perl-like syntax with lookarounds and backreferences.
+    Pcre,
+}
+
+/// Which part of a line a pattern has to match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Extent {
+    Anywhere,
+    /// `-w`: a match must not have word characters right before or after it.
+    Word,
+    /// `-x`: a match must span the whole line.
+    Line,
+}
+
+/// Any of several patterns, compiled into a single regular expression that
+/// searches bytes, which need not be valid UTF-8.
+#[derive(Debug)]
+pub enum Matcher {
+    Rust(regex::bytes::Regex),
+    /// fancy-regex only searches strings, so the valid UTF-8 parts of the
+    /// text are searched separately.
+    Pcre(fancy_regex::Regex),
+}
+
+impl Matcher {
+    /// Compiles `patterns`. An empty list matches nothing, as with an empty
+    /// `-f` file. With `multiline`, `^` and `$` match at the start and end
+    /// of every line of the text.
+    pub fn new(
+        patterns: &[String],
+        syntax: Syntax,
+        extent: Extent,
+        insensitive: bool,
+        multiline: bool,
+    ) -> Result<Matcher, String> {
+        let mut alternatives = Vec::with_capacity(patterns.len());
+        for original in patterns {
+            let pattern = match syntax {
+                Syntax::Fixed => regex::escape(original),
+                _ => original.to_string(),
+            };
+            // Compile each pattern on its own to tell which one is invalid.
+            build(&pattern, syntax, insensitive)
+                .map_err(|e| format!("Invalid pattern \"{}\": {}", original, e))?;
+            alternatives.push(format!("(?:{})", pattern));
+        }
+        let pattern = match alternatives.len() {
+            0 => r"[^\s\S]".to_string(),
+            _ => alternatives.join("|"),
+        };
+        let pattern = match (extent, syntax) {
+            (Extent::Anywhere, _) => pattern,
+            (Extent::Word, Syntax::Pcre) => format!(r"(?<!\w)(?:{})(?!\w)", pattern),
+            (Extent::Word, _) => format!(r"\b{{start-half}}(?:{})\b{{end-half}}", pattern),
+            (Extent::Line, _) => format!("^(?:{})$", pattern),
+        };
+        let pattern = match multiline {
+            true => format!("(?m){}", pattern),
+            false => pattern,
+        };
+        build(&pattern, syntax, insensitive)
+    }
+
+    pub fn is_match(&self, text: &[u8]) -> bool {
+        match self {
+            Matcher::Rust(re) => re.is_match(text),
+            // Errors are only possible when backtracking exceeds its limit.
+            Matcher::Pcre(re) => valid_parts(text).any(|(_, s)| re.is_match(s).unwrap_or(false)),
+        }
+    }
+
+    /// Returns the byte ranges of the successive non-overlapping matches in
+    /// `text`.
+    pub fn find_iter<'a>(&'a self, text: &'a [u8]) -> Box<dyn Iterator<Item = Range<usize>> + 'a> {
+        match self {
+            Matcher::Rust(re) => Box::new(re.find_iter(text).map(|m| m.range())),
+            Matcher::Pcre(re) => Box::new(valid_parts(text).flat_map(move |(start, s)| {
+                re.find_iter(s)
+                    .map_while(|m| m.ok())
+                    .map(move |m| start + m.start()..start + m.end())
+            })),
+        }
+    }
+
+    /// Returns the byte ranges of the successive non-overlapping matches in
+    /// `text`, each with `template` expanded for it: `$1` or `${name}` stand
+    /// for the text of a group, and `$$` for a `$`.
+    pub fn replacements(&self, text: &[u8], template: &str) -> Vec<(Range<usize>, Vec<u8>)> {
+        match self {
+            Matcher::Rust(re) => re
+                .captures_iter(text)
+                .map(|caps| {
+                    let mut replacement = Vec::new();
+                    caps.expand(template.as_bytes(), &mut replacement);
+                    (caps.get(0).unwrap().range(), replacement)
+                })
+                .collect(),
+            Matcher::Pcre(re) => valid_parts(text)
+                .flat_map(|(start, s)| {
+                    re.captures_iter(s)
+                        .map_while(|caps| caps.ok())
+                        .map(move |caps| {
+                            let mut replacement = String::new();
+                            caps.expand(template, &mut replacement);
+                            let m = caps.get(0).unwrap();
+                            (start + m.start()..start + m.end(), replacement.into_bytes())
+                        })
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns `text` with every match replaced by `template`, as expanded by
+    /// `replacements`.
+    pub fn replace_all(&self, text: &[u8], template: &str) -> Vec<u8> {
+        splice(text, self.replacements(text, template))
+    }
+}
+
+/// Replaces the given ranges of `text`, which must be in order and not
+/// overlap.
+pub fn splice(
+    text: &[u8],
+    replacements: impl IntoIterator<Item = (Range<usize>, Vec<u8>)>,
+) -> Vec<u8> {
+    let mut result = Vec::with_capacity(text.len());
+    let mut last = 0;
+    for (range, replacement) in replacements {
+        result.extend_from_slice(&text[last..range.start]);
+        result.extend_from_slice(&replacement);
+        last = range.end;
+    }
+    result.extend_from_slice(&text[last..]);
+    result
+}
+
+// Splits `text` into its valid UTF-8 parts, with their offsets.
+fn valid_parts(text: &[u8]) -> impl Iterator<Item = (usize, &str)> {
+    let mut offset = 0;
+    text.utf8_chunks().map(move |chunk| {
+        let start = offset;
+        offset += chunk.valid().len() + chunk.invalid().len();
+        (start, chunk.valid())
+    })
+}
+
+fn build(pattern: &str, syntax: Syntax, insensitive: bool) -> Result<Matcher, String> {
+    match syntax {
+        Syntax::Pcre => fancy_regex::RegexBuilder::new(pattern)
+            .case_insensitive(insensitive)
+            .build()
+            .map(Matcher::Pcre)
+            .map_err(|e| e.to_string()),
+        _ => RegexBuilder::new(pattern)
+            .case_insensitive(insensitive)
+            .build()
+            .map(Matcher::Rust)
+            .map_err(|e| e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Extent, Matcher, Syntax};
+
+    fn matcher(patterns: &[&str], syntax: Syntax, extent: Extent) -> Matcher {
+        let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+        Matcher::new(&patterns, syntax, extent, false, false).unwrap()
+    }
+
+    fn find(matcher: &Matcher, text: &[u8]) -> Vec<String> {
+        matcher
+            .find_iter(text)
+            .map(|r| String::from_utf8_lossy(&text[r]).into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_fixed() {
+        let m = matcher(&["a.c", "(x"], Syntax::Fixed, Extent::Anywhere);
+        assert_eq!(find(&m, b"abc a.c (x"), ["a.c", "(x"]);
+        assert!(!m.is_match(b"abc"));
+    }
+
+    #[test]
+    fn test_word() {
+        let m = matcher(&["foo", "-"], Syntax::Regex, Extent::Word);
+        assert_eq!(find(&m, b"foo food foo_ (foo) a - b"), ["foo", "foo", "-"]);
+        assert!(!m.is_match(b"a-b"));
+
+        let m = matcher(&["foo"], Syntax::Pcre, Extent::Word);
+        assert_eq!(find(&m, b"foo food (foo)"), ["foo", "foo"]);
+    }
+
+    #[test]
+    fn test_line() {
+        let m = matcher(&["fo+", "bar"], Syntax::Regex, Extent::Line);
+        assert!(m.is_match(b"foo"));
+        assert!(m.is_match(b"bar"));
+        assert!(!m.is_match(b"foobar"));
+        assert!(!m.is_match(b"a foo"));
+    }
+
+    #[test]
+    fn test_multiline() {
+        let patterns = vec![r"fn \w+\(\n\s+self".to_string(), "^}$".to_string()];
+        let m = Matcher::new(&patterns, Syntax::Regex, Extent::Anywhere, false, true).unwrap();
+        assert_eq!(
+            find(&m, b"fn new(\n    self,\n) {\n}\n"),
+            ["fn new(\n    self", "}"]
+        );
+    }
+
+    #[test]
+    fn test_pcre() {
+        let m = matcher(&[r"\w+(?= dog)", r"(o)\1"], Syntax::Pcre, Extent::Anywhere);
+        assert_eq!(find(&m, b"the lazy dog says woof"), ["lazy", "oo"]);
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        let m = matcher(&["b.", "c"], Syntax::Regex, Extent::Anywhere);
+        assert_eq!(find(&m, b"ab\xffc"), ["c"]);
+
+        let m = matcher(&[r"\w+"], Syntax::Pcre, Extent::Anywhere);
+        assert_eq!(find(&m, b"ab\xffcd\xfe"), ["ab", "cd"]);
+        assert!(!m.is_match(b"\xff\xfe"));
+    }
+
+    #[test]
+    fn test_replace_all() {
+        let m = matcher(&[r"(\w+)@(?<host>\w+)"], Syntax::Regex, Extent::Anywhere);
+        assert_eq!(
+            m.replace_all(b"a@b, \xffc@d", "${host}:$1$$"),
+            b"b:a$, \xffd:c$"
+        );
+
+        let m = matcher(&[r"(\w)(?=\d)"], Syntax::Pcre, Extent::Anywhere);
+        assert_eq!(m.replace_all(b"a1 b \xffc2", "<$1>"), b"<a>1 b \xff<c>2");
+
+        // Fixed strings have no groups
+        let m = matcher(&["$1"], Syntax::Fixed, Extent::Anywhere);
+        assert_eq!(m.replace_all(b"cost: $1", "${0}0"), b"cost: $10");
+    }
+
+    #[test]
+    fn test_invalid() {
+        let patterns = vec!["ok".to_string(), "*foo".to_string()];
+        let err =
+            Matcher::new(&patterns, Syntax::Regex, Extent::Anywhere, false, false).unwrap_err();
+        assert!(err.starts_with("Invalid pattern \"*foo\""));
+        assert!(Matcher::new(&patterns, Syntax::Fixed, Extent::Anywhere, false, false).is_ok());
+    }
+
+    #[test]
+    fn test_no_patterns() {
+        let m = matcher(&[], Syntax::Regex, Extent::Anywhere);
+        assert!(!m.is_match(b""));
+        assert!(!m.is_match(b"anything"));
+    }
+}
@@ -0,0 +1,394 @@
+use std::{
+    io::{self, BufWriter, IsTerminal, Write},
+    ops::Range,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::SyncSender,
+    },
+    time::Instant,
+};
+
+use crate::{
+    Config, Line, Message,
+    color::Colors,
+    count_lines,
+    json::{self, Stats},
+    pattern::{Matcher, splice},
+};
+
+/// Receives the lines selected by `find_lines` as soon as they are found.
+pub trait Sink {
+    /// Handles a matching or context line. Returning `Ok(false)` stops the
+    /// search of the current input.
+    fn line(&mut self, line: Line) -> io::Result<bool>;
+}
+
+/// Counts matching lines without keeping them.
+#[derive(Debug, Default)]
+pub struct Count {
+    pub count: usize,
+    /// Stop reading once this many lines matched.
+    pub limit: Option<usize>,
+}
+
+impl Sink for Count {
+    fn line(&mut self, line: Line) -> io::Result<bool> {
+        if line.is_match {
+            self.count += 1;
+        }
+        Ok(self.limit.is_none_or(|limit| self.count < limit))
+    }
+}
+
+/// Where a `Printer` sends the output of an input before it is fully
+/// searched. Only the input that is next in order is written as it is
+/// found; the output of the others waits until the inputs before them are
+/// written.
+#[derive(Clone)]
+pub struct Stream<'a> {
+    pub tx: SyncSender<Message>,
+    /// The index of the input being written.
+    pub next: &'a AtomicUsize,
+    /// The index of the current input.
+    pub index: usize,
+    /// How much output to gather before sending it.
+    pub chunk: usize,
+}
+
+/// Formats the output for one input at a time, in the format selected by
+/// the options. The output is kept in memory until it can be written, so
+/// that inputs can be searched in parallel.
+#[derive(Clone)]
+pub struct Printer<'a> {
+    config: &'a Config,
+    pattern: &'a Matcher,
+    colors: &'a Colors,
+    out: Vec<u8>,
+    stream: Option<Stream<'a>>,
+    show_filename: bool,
+    /// Set when context lines are printed, which calls for separators
+    /// between groups of lines.
+    context: bool,
+    /// Ends the lines that are printed: a newline, or NUL with -z.
+    terminator: u8,
+    /// The name of the current input.
+    name: String,
+    /// The number of the last line printed from the current input.
+    last_line: Option<usize>,
+    /// Whether a line of the current input was selected.
+    selected: bool,
+    /// For --json: when the search of the current input started, and what
+    /// it found so far.
+    started: Instant,
+    stats: Stats,
+}
+
+impl<'a> Printer<'a> {
+    pub fn new(
+        config: &'a Config,
+        pattern: &'a Matcher,
+        colors: &'a Colors,
+        show_filename: bool,
+        context: bool,
+    ) -> Printer<'a> {
+        Printer {
+            config,
+            pattern,
+            colors,
+            out: Vec::new(),
+            stream: None,
+            show_filename,
+            context,
+            terminator: if config.null_data { b'\0' } else { b'\n' },
+            name: String::new(),
+            last_line: None,
+            selected: false,
+            started: Instant::now(),
+            stats: Stats::default(),
+        }
+    }
+
+    /// Starts the output for the input shown as `name`.
+    pub fn start(&mut self, name: &str) {
+        name.clone_into(&mut self.name);
+        self.last_line = None;
+        self.selected = false;
+        self.started = Instant::now();
+        self.stats = Stats::default();
+    }
+
+    /// Sends the output of the current input to `stream` once it may be
+    /// written.
+    pub fn stream_to(&mut self, stream: Stream<'a>) {
+        self.stream = Some(stream);
+    }
+
+    // Sends the output gathered so far if the current input is being
+    // written. Returns false if the output is no longer wanted.
+    fn send(&mut self) -> bool {
+        let Some(stream) = &self.stream else {
+            return true;
+        };
+        if self.out.len() < stream.chunk || stream.next.load(Ordering::Relaxed) != stream.index {
+            return true;
+        }
+        self.stats.bytes_printed += self.out.len() as u64;
+        let output = std::mem::take(&mut self.out);
+        stream.tx.send(Message::Output(output)).is_ok()
+    }
+
+    /// Counts bytes read from the current input.
+    pub fn searched(&mut self, bytes: u64) {
+        self.stats.bytes_searched += bytes;
+    }
+
+    /// Ends the output for the current input, returning its statistics.
+    /// With --json, this prints the `end` message if lines were printed.
+    pub fn finish(&mut self) -> io::Result<Stats> {
+        self.stats.elapsed = self.started.elapsed();
+        self.stats.searches = 1;
+        self.stats.searches_with_match = self.selected.into();
+        self.stats.bytes_printed += self.out.len() as u64;
+        if self.config.json && self.printed_lines() {
+            self.message(&json::end(&self.name, self.stats))?;
+        }
+        Ok(self.stats)
+    }
+
+    fn message(&mut self, message: &serde_json::Value) -> io::Result<()> {
+        serde_json::to_writer(&mut self.out, message)?;
+        self.out.push(b'\n');
+        Ok(())
+    }
+
+    pub fn colors(&self) -> &'a Colors {
+        self.colors
+    }
+
+    /// Returns the output for the current input.
+    pub fn take(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.out)
+    }
+
+    /// Returns true if matching or context lines of the current input were
+    /// printed, rather than a count or a name.
+    pub fn printed_lines(&self) -> bool {
+        self.last_line.is_some()
+    }
+
+    /// Returns true if a line of the current input was selected.
+    pub fn selected(&self) -> bool {
+        self.selected
+    }
+
+    /// Prints the number of matching lines of the current input for `-c`.
+    pub fn count(&mut self, count: usize) -> io::Result<()> {
+        let mut prefix = String::new();
+        if self.show_filename {
+            prefix += &self.colors.paint(&self.colors.filename, &self.name);
+            prefix += &self.colors.paint(&self.colors.separator, ":");
+        }
+        writeln!(self.out, "{}{}", prefix, count)
+    }
+
+    /// Prints the name of the current input for `-l` and `-L`.
+    pub fn name(&mut self) -> io::Result<()> {
+        writeln!(
+            self.out,
+            "{}",
+            self.colors.paint(&self.colors.filename, &self.name)
+        )
+    }
+
+    /// Reports a match in a binary input instead of printing the line.
+    /// With --json, binary inputs are left out.
+    pub fn binary_matches(&mut self) -> io::Result<()> {
+        if self.config.json {
+            return Ok(());
+        }
+        writeln!(self.out, "Binary file {} matches", self.name)
+    }
+
+    // Builds the file name, line number and byte offset part of a line.
+    fn prefix(&self, line_number: usize, byte_offset: usize, sep: &str) -> String {
+        let colors = self.colors;
+        let sep = colors.paint(&colors.separator, sep);
+        let mut prefix = String::new();
+        if self.show_filename {
+            prefix += &(colors.paint(&colors.filename, &self.name) + &sep);
+        }
+        if self.config.line_number {
+            prefix += &(colors.paint(&colors.line_number, &line_number.to_string()) + &sep);
+        }
+        if self.config.byte_offset {
+            prefix += &(colors.paint(&colors.byte_offset, &byte_offset.to_string()) + &sep);
+        }
+        prefix
+    }
+}
+
+impl Sink for Printer<'_> {
+    fn line(&mut self, line: Line) -> io::Result<bool> {
+        self.print(line)?;
+        Ok(self.send())
+    }
+}
+
+impl Printer<'_> {
+    fn print(&mut self, line: Line) -> io::Result<()> {
+        let config = self.config;
+        let colors = self.colors;
+        let terminator = self.terminator;
+        let text = line.text.strip_suffix(&[terminator]).unwrap_or(&line.text);
+        // With -U, a match may take in the terminator, so the pattern runs
+        // over all of the text.
+        let searched = if config.multiline { &line.text } else { text };
+        self.selected |= line.is_match;
+        if config.json {
+            return self.json_line(&line, searched);
+        }
+        if config.only_matching {
+            // Inverted matches have no matched parts to print.
+            if line.is_match && !config.invert_match {
+                let parts = match &config.replace {
+                    Some(template) => self.pattern.replacements(searched, template),
+                    None => self
+                        .pattern
+                        .find_iter(searched)
+                        .map(|m| (m.clone(), searched[m].to_vec()))
+                        .collect(),
+                };
+                for (m, part) in parts.into_iter().filter(|(m, _)| !m.is_empty()) {
+                    // With -U, the text may span several lines.
+                    let line_number = line.line_number + count(&searched[..m.start], terminator);
+                    let prefix = self.prefix(line_number, line.byte_offset + m.start, ":");
+                    let part = colors.paint_bytes(&colors.selected_match, &part);
+                    self.out.write_all(prefix.as_bytes())?;
+                    self.out.write_all(&part)?;
+                    self.out.write_all(&[terminator])?;
+                }
+            }
+            return Ok(());
+        }
+
+        // The separator before the first group of an input is left to the
+        // `Writer`, which knows whether another input printed lines before.
+        let contiguous = self.last_line.is_none_or(|n| line.line_number == n + 1);
+        if self.context && !contiguous {
+            writeln!(self.out, "{}", colors.paint(&colors.separator, "--"))?;
+        }
+        self.last_line = Some(line.line_number + count(text, terminator));
+
+        // GNU grep marks matches with `:` and context with `-`, and
+        // highlights matches in context lines only when they are the lines
+        // that were not selected by -v.
+        let (sep, code) = match (line.is_match, config.invert_match) {
+            (true, false) => (":", colors.selected_match.as_str()),
+            (true, true) => (":", ""),
+            (false, false) => ("-", ""),
+            (false, true) => ("-", colors.context_match.as_str()),
+        };
+        let prefix = self.prefix(line.line_number, line.byte_offset, sep);
+        // The parts of matches past the end of the text are left out.
+        let clip = |m: Range<usize>| m.start.min(text.len())..m.end.min(text.len());
+        let text = match &config.replace {
+            // Only the selected lines are changed, with the replacements
+            // highlighted as the matches would be.
+            Some(template) if line.is_match && !config.invert_match => {
+                let replacements = self.pattern.replacements(searched, template);
+                splice(
+                    text,
+                    replacements
+                        .into_iter()
+                        .map(|(m, r)| (clip(m), colors.paint_bytes(code, &r))),
+                )
+            }
+            _ => colors.highlight(code, text, self.pattern.find_iter(searched).map(clip)),
+        };
+        self.out.write_all(prefix.as_bytes())?;
+        self.out.write_all(&text)?;
+        self.out.write_all(&[terminator])
+    }
+
+    // Prints a `match` or `context` message, after the `begin` message for
+    // the first line of an input.
+    fn json_line(&mut self, line: &Line, searched: &[u8]) -> io::Result<()> {
+        if !self.printed_lines() {
+            self.message(&json::begin(&self.name))?;
+        }
+        let lines = count_lines(&line.text, self.terminator);
+        self.last_line = Some(line.line_number + lines - 1);
+
+        let mut matches = Vec::new();
+        if line.is_match {
+            if !self.config.invert_match {
+                matches.extend(self.pattern.find_iter(searched).filter(|m| !m.is_empty()));
+            }
+            self.stats.matched_lines += lines as u64;
+            self.stats.matches += matches.len() as u64;
+        }
+        let message = json::line(
+            line.is_match,
+            &self.name,
+            &line.text,
+            line.line_number,
+            line.byte_offset,
+            &matches,
+        );
+        self.message(&message)
+    }
+}
+
+// Counts the occurrences of `byte` in `text`.
+fn count(text: &[u8], byte: u8) -> usize {
+    text.iter().filter(|&&b| b == byte).count()
+}
+
+/// Writes the output of the inputs to stdout in the order they were given.
+pub struct Writer {
+    out: Box<dyn Write>,
+    /// The `--` printed between groups of lines from different inputs, if
+    /// context is printed.
+    separator: Option<String>,
+    /// Whether lines have been printed.
+    printed: bool,
+}
+
+impl Writer {
+    pub fn new(colors: &Colors, context: bool) -> Writer {
+        // Output is shown as soon as it is found on a terminal, while larger
+        // writes are faster through a pipe.
+        let stdout = io::stdout().lock();
+        let out: Box<dyn Write> = if io::stdout().is_terminal() {
+            Box::new(stdout)
+        } else {
+            Box::new(BufWriter::new(stdout))
+        };
+        Writer {
+            out,
+            separator: context.then(|| colors.paint(&colors.separator, "--") + "\n"),
+            printed: false,
+        }
+    }
+
+    /// Writes the output of one input. `lines` tells matching and context
+    /// lines from counts and file names, which are never separated.
+    pub fn write(&mut self, output: &[u8], lines: bool) -> io::Result<()> {
+        if output.is_empty() {
+            return Ok(());
+        }
+        if lines {
+            if let Some(separator) = &self.separator
+                && self.printed
+            {
+                self.out.write_all(separator.as_bytes())?;
+            }
+            self.printed = true;
+        }
+        self.out.write_all(output)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
@@ -0,0 +1,303 @@
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::mpsc,
+};
+
+use globset::GlobMatcher;
+use ignore::{DirEntry, WalkBuilder, WalkState};
+
+use crate::{Config, io_message};
+
+/// Which files `find_files` yields for the paths given on the command line.
+#[derive(Debug, Default, Clone)]
+pub struct Walk {
+    /// Search directories, with `-r` or `-R`.
+    pub recursive: bool,
+    /// `-R`: follow the symbolic links found in directories, which `-r`
+    /// skips.
+    pub follow: bool,
+    /// Search only files with a name matching one of these.
+    pub include: Vec<GlobMatcher>,
+    pub exclude: Vec<GlobMatcher>,
+    /// Skip the directories with a name matching one of these.
+    pub exclude_dir: Vec<GlobMatcher>,
+    /// Skip what `.ignore` files ignore, and within git repositories what
+    /// `.gitignore` and git's exclude files ignore.
+    pub respect_ignore: bool,
+}
+
+impl From<&Config> for Walk {
+    fn from(config: &Config) -> Walk {
+        Walk {
+            recursive: config.recursive || config.dereference_recursive,
+            follow: config.dereference_recursive,
+            include: config.include.clone(),
+            exclude: config.exclude.clone(),
+            exclude_dir: config.exclude_dir.clone(),
+            respect_ignore: config.respect_ignore,
+        }
+    }
+}
+
+/// A problem found while looking for files, reported on stderr.
+#[derive(Debug)]
+pub struct WalkError {
+    message: String,
+    /// Set for symbolic link loops, which do not change the exit status.
+    pub warning: bool,
+}
+
+impl fmt::Display for WalkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl WalkError {
+    fn new(message: String) -> WalkError {
+        WalkError {
+            message,
+            warning: false,
+        }
+    }
+}
+
+impl Walk {
+    // Checks the name of a file against --include and --exclude.
+    fn is_selected(&self, path: &Path) -> bool {
+        let Some(name) = path.file_name() else {
+            return true;
+        };
+        (self.include.is_empty() || self.include.iter().any(|g| g.is_match(name)))
+            && !self.exclude.iter().any(|g| g.is_match(name))
+    }
+
+    fn builder(&self, root: &Path) -> WalkBuilder {
+        let mut builder = WalkBuilder::new(root);
+        let ignore = self.respect_ignore;
+        // Unlike ripgrep, hidden files are searched as GNU grep does.
+        builder
+            .standard_filters(false)
+            .git_ignore(ignore)
+            .git_global(ignore)
+            .git_exclude(ignore)
+            .ignore(ignore)
+            .parents(ignore)
+            .follow_links(self.follow);
+        let exclude_dir = self.exclude_dir.clone();
+        builder.filter_entry(move |e| {
+            e.depth() == 0
+                || !e.file_type().is_some_and(|t| t.is_dir())
+                || !exclude_dir.iter().any(|g| g.is_match(e.file_name()))
+        });
+        builder
+    }
+
+    // Walks the directory `root` in parallel. Each file or error is sent
+    // with the path it was found at, whose order, compared one component
+    // at a time, is that of the sorted walk.
+    fn find_in(&self, root: &Path, threads: usize) -> Vec<Result<PathBuf, WalkError>> {
+        let (tx, rx) = mpsc::channel();
+        self.builder(root)
+            .threads(threads)
+            .build_parallel()
+            .run(|| {
+                let tx = tx.clone();
+                Box::new(move |result| {
+                    let at = match &result {
+                        Ok(entry) => entry.path().to_path_buf(),
+                        Err(e) => error_path(e).unwrap_or(root).to_path_buf(),
+                    };
+                    if let Some(file) = to_file(result, self) {
+                        // The receiver outlives the walk.
+                        tx.send((at, file)).unwrap();
+                    }
+                    WalkState::Continue
+                })
+            });
+        drop(tx);
+        let mut found: Vec<_> = rx.into_iter().collect();
+        found.sort_by(|a, b| a.0.cmp(&b.0));
+        found.into_iter().map(|(_, file)| file).collect()
+    }
+}
+
+/// Finds the files to search, or errors for the paths that cannot be
+/// searched. `-` stands for stdin. Directories are walked with `threads`
+/// threads, and what is found in them is put back in the order of a walk
+/// that visits the entries of each directory sorted by name, so that the
+/// output depends neither on the file system nor on the threads.
+pub fn find_files(
+    paths: &[PathBuf],
+    walk: &Walk,
+    threads: usize,
+) -> Vec<Result<PathBuf, WalkError>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.as_os_str() == "-" {
+            files.push(Ok(path.clone()));
+        } else if !walk.recursive {
+            if path.is_dir() {
+                let err = WalkError::new(format!("{} is a directory", path.display()));
+                files.push(Err(err));
+            } else if walk.is_selected(path) {
+                // Errors such as a missing file are reported when it is
+                // opened.
+                files.push(Ok(path.clone()));
+            }
+        } else if let Err(e) = fs::metadata(path) {
+            files.push(Err(WalkError::new(format!(
+                "{}: {}",
+                path.display(),
+                io_message(&e)
+            ))));
+        } else {
+            files.extend(walk.find_in(path, threads));
+        }
+    }
+    files
+}
+
+// Keeps the regular files, including the targets of the links followed with
+// -R. Other links, devices and the like are skipped as GNU grep does.
+fn to_file(
+    result: Result<DirEntry, ignore::Error>,
+    walk: &Walk,
+) -> Option<Result<PathBuf, WalkError>> {
+    match result {
+        Ok(entry) => {
+            let is_file = entry.file_type().is_some_and(|t| t.is_file());
+            (is_file && walk.is_selected(entry.path())).then(|| Ok(entry.into_path()))
+        }
+        Err(e) => Some(Err(describe(&e))),
+    }
+}
+
+fn describe(err: &ignore::Error) -> WalkError {
+    match err {
+        ignore::Error::WithDepth { err, .. } => describe(err),
+        ignore::Error::WithPath { path, err } => match err.as_ref() {
+            ignore::Error::Io(e) => {
+                WalkError::new(format!("{}: {}", path.display(), io_message(e)))
+            }
+            err => describe(err),
+        },
+        ignore::Error::Loop { child, .. } => WalkError {
+            message: format!("{}: warning: recursive directory loop", child.display()),
+            warning: true,
+        },
+        err => WalkError::new(err.to_string()),
+    }
+}
+
+// Returns the path that `err` is about, if any.
+fn error_path(err: &ignore::Error) -> Option<&Path> {
+    match err {
+        ignore::Error::WithDepth { err, .. } => error_path(err),
+        ignore::Error::WithPath { path, .. } => Some(path),
+        ignore::Error::Loop { child, .. } => Some(child),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Walk, find_files};
+    use rand::{Rng, distributions::Alphanumeric};
+    use std::path::PathBuf;
+
+    fn find(paths: &[&str], walk: &Walk) -> Vec<Result<String, String>> {
+        let paths: Vec<PathBuf> = paths.iter().map(PathBuf::from).collect();
+        find_files(&paths, walk, 2)
+            .into_iter()
+            .map(|r| {
+                r.map(|p| p.display().to_string())
+                    .map_err(|e| e.to_string())
+            })
+            .collect()
+    }
+
+    fn glob(pattern: &str) -> globset::GlobMatcher {
+        globset::Glob::new(pattern).unwrap().compile_matcher()
+    }
+
+    #[test]
+    fn test_find_files() {
+        // Verify that the function finds a file known to exist
+        let walk = Walk::default();
+        let files = find(&["./tests/inputs/fox.txt"], &walk);
+        assert_eq!(files, [Ok("./tests/inputs/fox.txt".to_string())]);
+
+        // The function should reject a directory without the recursive option
+        let files = find(&["./tests/inputs"], &walk);
+        assert_eq!(files, [Err("./tests/inputs is a directory".to_string())]);
+
+        // Verify the function recurses to find four files in the directory,
+        // in order
+        let walk = Walk {
+            recursive: true,
+            ..Walk::default()
+        };
+        let files: Vec<String> = find(&["./tests/inputs"], &walk)
+            .into_iter()
+            .map(|r| r.unwrap().replace('\\', "/"))
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                "./tests/inputs/bustle.txt",
+                "./tests/inputs/empty.txt",
+                "./tests/inputs/fox.txt",
+                "./tests/inputs/nobody.txt",
+            ]
+        );
+
+        // Generate a random string to represent a nonexistent file
+        let bad: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(7)
+            .map(char::from)
+            .collect();
+
+        // Verify that the function returns the bad file as an error when
+        // walking, and leaves it to be opened otherwise
+        let files = find(&[&bad], &walk);
+        assert_eq!(files.len(), 1);
+        assert!(files[0].as_ref().unwrap_err().starts_with(&bad));
+        let files = find(&[&bad], &Walk::default());
+        assert_eq!(files, [Ok(bad)]);
+    }
+
+    #[test]
+    fn test_find_files_globs() {
+        let walk = Walk {
+            recursive: true,
+            include: vec![glob("*o*.txt")],
+            exclude: vec![glob("b*")],
+            ..Walk::default()
+        };
+        let files = find(&["./tests/inputs", "-"], &walk);
+        assert_eq!(
+            files,
+            [
+                Ok("./tests/inputs/fox.txt".to_string()),
+                Ok("./tests/inputs/nobody.txt".to_string()),
+                Ok("-".to_string()),
+            ]
+        );
+
+        // Directories are only skipped below the search paths
+        let walk = Walk {
+            recursive: true,
+            exclude_dir: vec![glob("inputs")],
+            ..Walk::default()
+        };
+        assert_eq!(find(&["./tests/inputs/"], &walk).len(), 4);
+        assert!(
+            find(&["./tests"], &walk)
+                .iter()
+                .all(|r| { r.as_ref().is_ok_and(|f| !f.contains("inputs")) })
+        );
+    }
+}
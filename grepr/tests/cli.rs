@@ -53,11 +53,11 @@ fn dies_bad_pattern() -> Result<()> {
 #[test]
 fn warns_bad_file() -> Result<()> {
     let bad = gen_bad_file();
-    let expected = format!("{bad}: .* [(]os error 2[)]");
     Command::cargo_bin(PRG)?
         .args(["foo", &bad])
         .assert()
-        .stderr(predicate::str::is_match(expected)?);
+        .code(2)
+        .stderr(format!("grepr: {bad}: No such file or directory\n"));
     Ok(())
 }
 
@@ -281,3 +281,794 @@ fn stdin_insensitive_count() -> Result<()> {
     assert_eq!(stdout, expected);
     Ok(())
 }
+
+// --------------------------------------------------
+#[test]
+fn bustle_line_number() -> Result<()> {
+    run(
+        &["-n", "The", BUSTLE],
+        "tests/expected/bustle.txt.the.capitalized.line_number",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn bustle_byte_offset() -> Result<()> {
+    run(
+        &["--byte-offset", "the", BUSTLE],
+        "tests/expected/bustle.txt.the.lowercase.byte_offset",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn nobody_line_number_byte_offset() -> Result<()> {
+    run(
+        &["-nbi", "nobody", NOBODY, FOX],
+        "tests/expected/nobody.txt.insensitive.line_number.byte_offset",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn with_filename() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["-H", "fox", FOX])
+        .assert()
+        .success()
+        .stdout("tests/inputs/fox.txt:The quick brown fox jumps over the lazy dog.\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn multiple_files_no_filename() -> Result<()> {
+    run(
+        &["-h", "The", BUSTLE, EMPTY, FOX, NOBODY],
+        "tests/expected/all.the.capitalized.no_filename",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn no_filename_overrides_with_filename() -> Result<()> {
+    run(
+        &["-H", "--no-filename", "The", BUSTLE, EMPTY, FOX, NOBODY],
+        "tests/expected/all.the.capitalized.no_filename",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn files_with_matches() -> Result<()> {
+    run(
+        &["-l", "The", BUSTLE, EMPTY, FOX, NOBODY],
+        "tests/expected/all.the.capitalized.files_with_matches",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn files_without_match() -> Result<()> {
+    run(
+        &["--files-without-match", "The", BUSTLE, EMPTY, FOX, NOBODY],
+        "tests/expected/all.the.capitalized.files_without_match",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_files_with_and_without_match() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["-l", "-L", "The", BUSTLE])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn stdin_label() -> Result<()> {
+    let input = fs::read_to_string(BUSTLE)?;
+    Command::cargo_bin(PRG)?
+        .args(["-Hc", "--label", "bustle", "The"])
+        .write_stdin(input.clone())
+        .assert()
+        .success()
+        .stdout("bustle:3\n");
+
+    Command::cargo_bin(PRG)?
+        .args(["-l", "The", "-", FOX])
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout("(standard input)\ntests/inputs/fox.txt\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn bustle_context() -> Result<()> {
+    run(
+        &["-n", "-C", "1", "The", BUSTLE],
+        "tests/expected/bustle.txt.the.capitalized.context",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn bustle_before_context() -> Result<()> {
+    // -A and -B take precedence over -C
+    run(
+        &["--context=3", "--after-context=0", "-n", "The", BUSTLE],
+        "tests/expected/bustle.txt.the.capitalized.before_context",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn multiple_files_context() -> Result<()> {
+    run(
+        &[
+            "-A", "2", "-B", "1", "-i", "the", BUSTLE, EMPTY, FOX, NOBODY,
+        ],
+        "tests/expected/all.the.lowercase.insensitive.context",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn bustle_only_matching() -> Result<()> {
+    run(
+        &["-onbi", "the", BUSTLE],
+        "tests/expected/bustle.txt.the.lowercase.insensitive.only_matching",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn bustle_max_count() -> Result<()> {
+    run(
+        &["--max-count", "2", "-A", "1", "-n", "The", BUSTLE],
+        "tests/expected/bustle.txt.the.capitalized.max_count",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn max_count_count() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["-c", "-m", "2", "The", BUSTLE])
+        .assert()
+        .success()
+        .stdout("2\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn color_always() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["--color=always", "-n", "-C1", "-i", "the", BUSTLE, FOX])
+        .env_remove("GREP_COLORS")
+        .assert()
+        .success()
+        .stdout(fs::read_to_string(
+            "tests/expected/bustle.fox.the.insensitive.color",
+        )?);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn color_grep_colors() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["--colour=always", "-H", "fox", FOX])
+        .env("GREP_COLORS", "ms=04:fn=:se=:ne")
+        .assert()
+        .success()
+        .stdout(
+            "tests/inputs/fox.txt:The quick brown \x1b[04mfox\x1b[m jumps over the lazy dog.\n",
+        );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn color_auto_not_terminal() -> Result<()> {
+    run(
+        &["--color", "The", BUSTLE],
+        "tests/expected/bustle.txt.the.capitalized",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn fixed_strings() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["-F", "dog.", FOX])
+        .assert()
+        .success()
+        .stdout("The quick brown fox jumps over the lazy dog.\n");
+
+    Command::cargo_bin(PRG)?
+        .args(["--fixed-strings", "-c", "d.g", FOX])
+        .assert()
+        .success()
+        .stdout("0\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn word_regexp() -> Result<()> {
+    run(
+        &["-w", "-i", "the", BUSTLE, FOX, NOBODY],
+        "tests/expected/all.the.insensitive.word",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn line_regexp() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["-x", "-n", "Until eternity.|The", BUSTLE])
+        .assert()
+        .success()
+        .stdout("9:Until eternity.\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn multiple_regexps() -> Result<()> {
+    // With -e, the first argument is a file
+    Command::cargo_bin(PRG)?
+        .args(["-e", "fox", "--regexp", "^Is", BUSTLE, FOX])
+        .assert()
+        .success()
+        .stdout(
+            "tests/inputs/bustle.txt:Is solemnest of industries\n\
+            tests/inputs/fox.txt:The quick brown fox jumps over the lazy dog.\n",
+        );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn patterns_file() -> Result<()> {
+    run(
+        &["-n", "-f", "tests/patterns/the_lazy.txt", BUSTLE, FOX],
+        "tests/expected/bustle.fox.patterns_file",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_patterns_file() -> Result<()> {
+    let bad = gen_bad_file();
+    Command::cargo_bin(PRG)?
+        .args(["-f", &bad, FOX])
+        .assert()
+        .code(2)
+        .stderr(format!("grepr: {bad}: No such file or directory\n"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_regexp() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["-e", "fox", "-e", "*foo", FOX])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(r#"Invalid pattern "*foo""#));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn perl_regexp() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["-P", "-o", r"\w+(?= fox)|(?<=lazy )\w+", FOX])
+        .assert()
+        .success()
+        .stdout("brown\ndog\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_conflicting_syntax() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["-F", "-P", "fox", FOX])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn quiet() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["-q", "fox", BUSTLE, FOX])
+        .assert()
+        .success()
+        .stdout("");
+
+    Command::cargo_bin(PRG)?
+        .args(["--quiet", "-c", "nothing here", BUSTLE, FOX])
+        .assert()
+        .code(1)
+        .stdout("");
+
+    // Without -q, finding nothing is not an error
+    Command::cargo_bin(PRG)?
+        .args(["nothing here", FOX])
+        .assert()
+        .success();
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn quiet_stops_at_first_match() -> Result<()> {
+    // A file that cannot be read after the match is never opened
+    let bad = gen_bad_file();
+    Command::cargo_bin(PRG)?
+        .args(["-q", "fox", FOX, &bad])
+        .assert()
+        .success()
+        .stderr("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn count_large_input() -> Result<()> {
+    let input = "a line of text\n".repeat(100_000);
+    Command::cargo_bin(PRG)?
+        .args(["-c", "line"])
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout("100000\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn binary_matches() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["-n", "foo"])
+        .write_stdin(b"foo\0bar\nbaz foo\n".to_vec())
+        .assert()
+        .success()
+        .stdout("Binary file (standard input) matches\n");
+
+    // Only matching binaries are reported
+    Command::cargo_bin(PRG)?
+        .args(["nothing here"])
+        .write_stdin(b"foo\0bar\n".to_vec())
+        .assert()
+        .success()
+        .stdout("");
+
+    // Counts are not affected
+    Command::cargo_bin(PRG)?
+        .args(["-c", "foo"])
+        .write_stdin(b"foo\0bar\nbaz foo\n".to_vec())
+        .assert()
+        .success()
+        .stdout("2\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn binary_as_text() -> Result<()> {
+    for args in [["-a", "foo"], ["--binary-files=text", "foo"]] {
+        Command::cargo_bin(PRG)?
+            .args(args)
+            .write_stdin(b"foo\0bar\nbaz\nbaz foo\n".to_vec())
+            .assert()
+            .success()
+            .stdout(b"foo\0bar\nbaz foo\n".as_slice());
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn binary_without_match() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["--binary-files=without-match", "-c", "foo"])
+        .write_stdin(b"foo\0bar\n".to_vec())
+        .assert()
+        .success()
+        .stdout("0\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn invalid_utf8() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["-o", "-b", "f.."])
+        .write_stdin(b"caf\xe9 foo\n\xff\n".to_vec())
+        .assert()
+        .success()
+        .stdout("5:foo\n");
+    Command::cargo_bin(PRG)?
+        .args(["caf"])
+        .write_stdin(b"caf\xe9 foo\n\xff\n".to_vec())
+        .assert()
+        .success()
+        .stdout(b"caf\xe9 foo\n".as_slice());
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn continues_after_bad_file() -> Result<()> {
+    let bad = gen_bad_file();
+    Command::cargo_bin(PRG)?
+        .args(["-c", "fox", &bad, FOX])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains(&bad))
+        .stdout(format!("{FOX}:1\n"));
+
+    // With -q, a selected line wins over the error
+    Command::cargo_bin(PRG)?
+        .args(["-q", "fox", &bad, FOX])
+        .assert()
+        .success();
+
+    Command::cargo_bin(PRG)?
+        .args(["-q", "nothing here", &bad, FOX])
+        .assert()
+        .code(2);
+    Ok(())
+}
+
+// --------------------------------------------------
+// A tree with ignore files, a vendored directory and links:
+//   .gitignore  .ignore  a.txt  b.log  ignored.txt  sub/c.txt
+//   sub/link.txt -> ../a.txt  sub/up -> ..  vendor/d.txt
+#[cfg(unix)]
+fn gen_tree() -> Result<tempfile::TempDir> {
+    let dir = tempfile::tempdir()?;
+    let root = dir.path();
+    fs::create_dir(root.join("sub"))?;
+    fs::create_dir(root.join("vendor"))?;
+    fs::write(root.join(".ignore"), "ignored.txt\n")?;
+    // Not in a git repository, so not used
+    fs::write(root.join(".gitignore"), "b.log\n")?;
+    for file in ["a.txt", "b.log", "ignored.txt", "sub/c.txt", "vendor/d.txt"] {
+        fs::write(root.join(file), format!("match in {file}\n"))?;
+    }
+    std::os::unix::fs::symlink("../a.txt", root.join("sub/link.txt"))?;
+    std::os::unix::fs::symlink("..", root.join("sub/up"))?;
+    Ok(dir)
+}
+
+fn output_in(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::cargo_bin(PRG)?
+        .current_dir(dir)
+        .args(args)
+        .output()?;
+    assert!(output.status.success());
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn recursive_tree() -> Result<()> {
+    let dir = gen_tree()?;
+    // Links are skipped, and the output is in order
+    assert_eq!(
+        output_in(dir.path(), &["-r", "match", "."])?,
+        "./a.txt:match in a.txt\n\
+         ./b.log:match in b.log\n\
+         ./ignored.txt:match in ignored.txt\n\
+         ./sub/c.txt:match in sub/c.txt\n\
+         ./vendor/d.txt:match in vendor/d.txt\n"
+    );
+    assert_eq!(
+        output_in(dir.path(), &["-rl", "--respect-ignore", "match", "."])?,
+        "./a.txt\n./b.log\n./sub/c.txt\n./vendor/d.txt\n"
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn recursive_globs() -> Result<()> {
+    let dir = gen_tree()?;
+    assert_eq!(
+        output_in(dir.path(), &["-rl", "--include=*.txt", "match", "."])?,
+        "./a.txt\n./ignored.txt\n./sub/c.txt\n./vendor/d.txt\n"
+    );
+    assert_eq!(
+        output_in(
+            dir.path(),
+            &["-rl", "--exclude=a.*", "--exclude-dir=vendor", "match", "."]
+        )?,
+        "./b.log\n./ignored.txt\n./sub/c.txt\n"
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn dereference_recursive() -> Result<()> {
+    let dir = gen_tree()?;
+    // Links are followed, but not into a loop
+    Command::cargo_bin(PRG)?
+        .current_dir(dir.path())
+        .args(["-Rl", "match", "."])
+        .assert()
+        .success()
+        .stdout("./a.txt\n./b.log\n./ignored.txt\n./sub/c.txt\n./sub/link.txt\n./vendor/d.txt\n")
+        .stderr("grepr: ./sub/up: warning: recursive directory loop\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn recursive_invalid_utf8_name() -> Result<()> {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    let dir = tempfile::tempdir()?;
+    fs::write(
+        dir.path().join(OsStr::from_bytes(b"bad\xff.txt")),
+        "match\n",
+    )?;
+    // The file is searched, and its name only changed for display
+    assert_eq!(
+        output_in(dir.path(), &["-r", "match", "."])?,
+        "./bad\u{fffd}.txt:match\n"
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn parallel_output_in_order() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut expected = String::new();
+    for i in 0..100 {
+        let name = format!("{i:03}.txt");
+        let text = "line\n".repeat(i * 100);
+        fs::write(dir.path().join(&name), &text)?;
+        if i > 0 {
+            expected += &format!("./{name}:{}\n", i * 100);
+        }
+    }
+    for threads in ["1", "4"] {
+        let output = output_in(dir.path(), &["-rc", "-j", threads, "line", "."])?;
+        assert_eq!(output, format!("./000.txt:0\n{expected}"));
+    }
+
+    // Inputs with more output than is gathered at once, separated by context
+    let dir = tempfile::tempdir()?;
+    let mut expected = String::new();
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        let text: String = (0..20000).map(|i| format!("{name} {i}\n")).collect();
+        fs::write(dir.path().join(name), &text)?;
+        if !expected.is_empty() {
+            expected += "--\n";
+        }
+        expected += &text;
+    }
+    for threads in ["1", "3"] {
+        let output = output_in(dir.path(), &["-rh", "-A1", "-j", threads, "txt", "."])?;
+        assert_eq!(output, expected);
+    }
+
+    // Directories walked in parallel, whose files sort between the others
+    let dir = tempfile::tempdir()?;
+    let mut expected = String::new();
+    for i in 0..10 {
+        for name in [
+            format!("{i}.txt"),
+            format!("{i}/a.txt"),
+            format!("{i}/b/c.txt"),
+        ] {
+            let path = dir.path().join(&name);
+            fs::create_dir_all(path.parent().unwrap())?;
+            fs::write(path, "match\n")?;
+        }
+        expected += &format!("./{i}/a.txt:match\n./{i}/b/c.txt:match\n./{i}.txt:match\n");
+    }
+    for threads in ["1", "4"] {
+        let output = output_in(dir.path(), &["-r", "-j", threads, "match", "."])?;
+        assert_eq!(output, expected);
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn replace() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["-n", "--replace", "${user} at $2", r"(?<user>\w+)@(\w+)"])
+        .write_stdin("mail john@example\nno address\nbob@test\n")
+        .assert()
+        .success()
+        .stdout("1:mail john at example\n3:bob at test\n");
+
+    // Only the replacements are printed with -o
+    Command::cargo_bin(PRG)?
+        .args(["-o", "--replace", "<$1>", r"(\w+)@"])
+        .write_stdin("mail john@example bob@test\n")
+        .assert()
+        .success()
+        .stdout("<john>\n<bob>\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn replace_in_place() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let file = dir.path().join("names.txt");
+    let original = "name: john\nage: 3\nname: bob";
+    fs::write(&file, original)?;
+    let file = file.to_str().unwrap();
+
+    Command::cargo_bin(PRG)?
+        .args(["--replace", "user: $1", "--in-place", "--backup", ".bak"])
+        .args([r"name: (\w+)", file])
+        .assert()
+        .success()
+        .stdout("");
+    assert_eq!(fs::read_to_string(file)?, "user: john\nage: 3\nuser: bob");
+    assert_eq!(fs::read_to_string(format!("{file}.bak"))?, original);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn replace_in_place_symlink() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    fs::write(dir.path().join("a.txt"), "name: john\n")?;
+    std::os::unix::fs::symlink("a.txt", dir.path().join("link.txt"))?;
+    output_in(
+        dir.path(),
+        &["--replace", "user", "--in-place", "name", "link.txt"],
+    )?;
+    // The file the link points to is edited, and the link is kept
+    assert!(fs::symlink_metadata(dir.path().join("link.txt"))?.is_symlink());
+    assert_eq!(
+        fs::read_to_string(dir.path().join("a.txt"))?,
+        "user: john\n"
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn replace_dry_run() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    fs::write(dir.path().join("a.txt"), "a\nb\nc\nb")?;
+    let expected = "--- a.txt\n+++ a.txt\n@@ -2 +2 @@\n-b\n+B\n\
+                    @@ -4 +4 @@\n-b\n\\ No newline at end of file\n\
+                    +B\n\\ No newline at end of file\n";
+    assert_eq!(
+        output_in(
+            dir.path(),
+            &["--replace", "B", "--in-place", "--dry-run", "b", "a.txt"]
+        )?,
+        expected
+    );
+    assert_eq!(fs::read_to_string(dir.path().join("a.txt"))?, "a\nb\nc\nb");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_in_place_stdin() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["--replace", "x", "--in-place", "a"])
+        .write_stdin("a\n")
+        .assert()
+        .failure()
+        .stderr("grepr: (standard input): cannot edit standard input\n");
+
+    Command::cargo_bin(PRG)?
+        .args(["--in-place", "a", FOX])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--replace <TEMPLATE>"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn multiline() -> Result<()> {
+    let input = "impl A {\n    fn new(\n        self,\n    ) {}\n    fn x(&self) {}\n}\n";
+    Command::cargo_bin(PRG)?
+        .args(["-Un", r"fn \w+\(\n\s+self"])
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout("2:    fn new(\n        self,\n");
+
+    // Without -U, no line matches on its own
+    Command::cargo_bin(PRG)?
+        .args(["-c", r"fn \w+\(\n\s+self"])
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout("0\n");
+
+    // Parts of a match are numbered after the line they start on
+    Command::cargo_bin(PRG)?
+        .args(["-Uon", r"self|\(\n"])
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout("2:(\n\n3:self\n5:self\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn null_data() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["-z", "-n", "^t"])
+        .write_stdin(b"one\ntwo\0three\0".to_vec())
+        .assert()
+        .success()
+        .stdout(b"2:three\0".as_slice());
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn json() -> Result<()> {
+    let output = Command::cargo_bin(PRG)?
+        .args(["--json", "-A1", "foo", "-", EMPTY])
+        .write_stdin("a foo foo\nb\nc\n")
+        .output()?;
+    assert!(output.status.success());
+    let messages: Vec<serde_json::Value> = String::from_utf8(output.stdout)?
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<_, _>>()?;
+    let types: Vec<&str> = messages
+        .iter()
+        .map(|m| m["type"].as_str().unwrap())
+        .collect();
+    assert_eq!(types, ["begin", "match", "context", "end", "summary"]);
+
+    let data = &messages[1]["data"];
+    assert_eq!(data["path"]["text"], "(standard input)");
+    assert_eq!(data["lines"]["text"], "a foo foo\n");
+    assert_eq!(data["line_number"], 1);
+    let offsets: Vec<_> = data["submatches"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|m| (m["start"].as_u64().unwrap(), m["end"].as_u64().unwrap()))
+        .collect();
+    assert_eq!(offsets, [(2, 5), (6, 9)]);
+    assert_eq!(messages[2]["data"]["absolute_offset"], 10);
+
+    // Inputs without matches only count in the summary
+    let stats = &messages[4]["data"]["stats"];
+    assert_eq!(stats["searches"], 2);
+    assert_eq!(stats["searches_with_match"], 1);
+    assert_eq!(stats["bytes_searched"], 14);
+    assert_eq!(stats["matches"], 2);
+    Ok(())
+}
@@ -1,74 +1,522 @@
 use std::{
+    borrow::Cow,
+    cmp::Reverse,
+    collections::HashMap,
     error::Error,
     fs::{self, File},
     io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    str::FromStr,
 };
 
-use clap::Parser;
+use base64::{Engine, prelude::BASE64_STANDARD};
+use clap::{Parser, ValueEnum};
+use csv_core::ReadRecordResult;
+use same_file::Handle;
+use tempfile::NamedTempFile;
 
 type MyResult<T> = Result<T, Box<dyn Error>>;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Text,
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Keep {
+    First,
+    Last,
+}
+
+fn parse_field(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("illegal field value: \"{}\"", s)),
+    }
+}
+
+fn parse_delimiter(s: &str) -> Result<u8, String> {
+    let bytes = s.as_bytes();
+    if bytes.len() != 1 {
+        return Err(format!("--delimiter \"{}\" must be a single byte", s));
+    }
+    Ok(bytes[0])
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Literal(String),
+    Count { width: usize },
+    Line,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CountFormat(Vec<Token>);
+
+impl Default for CountFormat {
+    // Same layout as GNU uniq: the count right-aligned in seven columns.
+    fn default() -> Self {
+        CountFormat(vec![
+            Token::Count { width: 7 },
+            Token::Literal(" ".to_string()),
+            Token::Line,
+        ])
+    }
+}
+
+impl FromStr for CountFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tokens = Vec::new();
+        let mut literal = String::new();
+        let mut chars = s.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('t') => literal.push('\t'),
+                    Some('n') => literal.push('\n'),
+                    Some('\\') => literal.push('\\'),
+                    Some(other) => {
+                        literal.push('\\');
+                        literal.push(other);
+                    }
+                    None => literal.push('\\'),
+                },
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(format!("unterminated placeholder in \"{}\"", s)),
+                        }
+                    }
+                    let token = match name.as_str() {
+                        "count" => Token::Count { width: 0 },
+                        "line" => Token::Line,
+                        _ => return Err(format!("unknown placeholder \"{{{}}}\"", name)),
+                    };
+                    if !literal.is_empty() {
+                        tokens.push(Token::Literal(std::mem::take(&mut literal)));
+                    }
+                    tokens.push(token);
+                }
+                '}' => return Err(format!("unmatched '}}' in \"{}\"", s)),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            tokens.push(Token::Literal(literal));
+        }
+
+        Ok(CountFormat(tokens))
+    }
+}
+
+impl CountFormat {
+    // Writes one record followed by the line's own terminator, or `\n` if the
+    // line had none.
+    fn write(&self, writer: &mut impl Write, line: &[u8], cnt: usize) -> io::Result<()> {
+        let body = trim_eol(line, false);
+        for token in &self.0 {
+            match token {
+                Token::Literal(s) => writer.write_all(s.as_bytes())?,
+                Token::Count { width } => write!(writer, "{:>width$}", cnt, width = width)?,
+                Token::Line => writer.write_all(body)?,
+            }
+        }
+        match &line[body.len()..] {
+            b"" => writer.write_all(b"\n"),
+            eol => writer.write_all(eol),
+        }
+    }
+}
+
 #[derive(Debug, Parser)]
 pub struct Config {
-    #[arg(default_value = "-", help = "Input file")]
-    in_file: String,
-    #[arg(help = "Output file")]
-    out_file: Option<String>,
+    #[arg(
+        value_name = "FILE",
+        default_value = "-",
+        help = "Input file and optional output file, or files to edit with --in-place"
+    )]
+    files: Vec<String>,
+    #[arg(
+        short = 'i',
+        long,
+        default_value_t = false,
+        help = "Edit files in place"
+    )]
+    in_place: bool,
     #[arg(short = 'c', long, default_value_t = false, help = "Show counts")]
     count: bool,
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        help = "Count line template using {count} and {line} (implies --count)"
+    )]
+    count_format: Option<CountFormat>,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Treat lines ending in \\r\\n and \\n as different"
+    )]
+    strict_eol: bool,
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Count all distinct lines, most frequent first"
+    )]
+    frequency: bool,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Show only the N most frequent lines (implies --frequency)"
+    )]
+    top: Option<usize>,
+    #[arg(long, value_enum, default_value_t = Format::Text, help = "Output format")]
+    format: Format,
+    #[arg(
+        long,
+        value_name = "FIELDS",
+        value_delimiter = ',',
+        value_parser = parse_field,
+        help = "Compare only these fields (e.g. 1,3)"
+    )]
+    key_fields: Vec<usize>,
+    #[arg(
+        long,
+        value_name = "DELIMITER",
+        default_value = ",",
+        value_parser = parse_delimiter,
+        help = "Field delimiter for --key-fields"
+    )]
+    delimiter: u8,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = Keep::First,
+        help = "Which line of each group to output"
+    )]
+    keep: Keep,
+}
+
+impl Config {
+    fn is_frequency(&self) -> bool {
+        self.frequency || self.top.is_some()
+    }
+
+    // Reads the next line into the empty `line`. With --key-fields a line is a
+    // whole CSV record, which spans several lines when a quoted field holds a
+    // newline. Returns the number of bytes read.
+    fn read_line(&self, file: &mut impl BufRead, line: &mut Vec<u8>) -> io::Result<usize> {
+        file.read_until(b'\n', line)?;
+        // A blank line is a record of its own, which the parser would skip.
+        if !self.key_fields.is_empty() && !trim_eol(line, false).is_empty() {
+            let mut parser = csv_core::ReaderBuilder::new()
+                .delimiter(self.delimiter)
+                .build();
+            let mut start = 0;
+            while !ends_record(&mut parser, &line[start..]) {
+                start = line.len();
+                if file.read_until(b'\n', line)? == 0 {
+                    break;
+                }
+            }
+        }
+        Ok(line.len())
+    }
+
+    // Returns the part of `line` that decides whether two lines are equal.
+    // With --key-fields the line is parsed as a quoted CSV row and the selected
+    // fields are encoded with a length prefix so that field boundaries are kept.
+    fn key<'a>(&self, line: &'a [u8]) -> Cow<'a, [u8]> {
+        let line = trim_eol(line, self.strict_eol);
+        if self.key_fields.is_empty() {
+            return Cow::Borrowed(line);
+        }
+
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .delimiter(self.delimiter)
+            .from_reader(line);
+        let mut record = csv::ByteRecord::new();
+        if reader.read_byte_record(&mut record).is_err() {
+            return Cow::Borrowed(line);
+        }
+
+        let mut key = Vec::new();
+        for field in &self.key_fields {
+            let value = record.get(field - 1).unwrap_or_default();
+            key.extend_from_slice(&value.len().to_le_bytes());
+            key.extend_from_slice(value);
+        }
+        Cow::Owned(key)
+    }
 }
 
 pub fn get_args() -> MyResult<Config> {
     let config = Config::parse();
 
+    if config.in_place {
+        if config.files.iter().any(|f| f == "-") {
+            return Err("--in-place cannot be used with standard input".into());
+        }
+    } else if config.files.len() > 2 {
+        return Err(format!(
+            "unexpected argument \"{}\"; use --in-place to edit several files",
+            config.files[2]
+        )
+        .into());
+    }
+
     Ok(config)
 }
 
 pub fn run(config: Config) -> MyResult<()> {
-    let mut writer: BufWriter<Box<dyn Write>> = match config.out_file {
-        Some(ref out_file) => BufWriter::new(Box::new(fs::File::create(out_file)?)),
-        None => BufWriter::new(Box::new(io::stdout())),
-    };
+    if config.in_place {
+        for file in &config.files {
+            rewrite(file, file, &config).map_err(|e| format!("{}: {}", file, e))?;
+        }
+        return Ok(());
+    }
 
-    let mut write = |line: &str, cnt: i32, conf: &Config| -> std::io::Result<()> {
-        if conf.count {
-            writer.write_all(format!("{:>4} ", cnt).as_bytes())?;
+    let in_file = &config.files[0];
+    match config.files.get(1) {
+        // Writing straight to the output would truncate the input before it
+        // has been read, so go through a temporary file instead.
+        Some(out_file) if is_same_input(in_file, out_file) => {
+            rewrite(in_file, out_file, &config).map_err(|e| format!("{}: {}", out_file, e).into())
         }
-        writer.write_all(line.as_bytes())?;
-        Ok(())
+        Some(out_file) => {
+            let file = open(in_file).map_err(|e| format!("{}: {}", in_file, e))?;
+            let writer = File::create(out_file).map_err(|e| format!("{}: {}", out_file, e))?;
+            uniq(file, Box::new(writer), &config)
+        }
+        None => {
+            let file = open(in_file).map_err(|e| format!("{}: {}", in_file, e))?;
+            uniq(file, Box::new(io::stdout()), &config)
+        }
+    }
+}
+
+// Tells whether `in_file` reads from `out_file`, including when standard input
+// is redirected from it.
+fn is_same_input(in_file: &str, out_file: &str) -> bool {
+    let Ok(out) = Handle::from_path(out_file) else {
+        return false;
     };
+    let input = match in_file {
+        "-" => Handle::stdin(),
+        _ => Handle::from_path(in_file),
+    };
+    input.is_ok_and(|input| input == out)
+}
+
+// Writes the result to a temporary file next to `out_file` and renames it
+// over the original once the whole input has been read.
+fn rewrite(in_file: &str, out_file: &str, config: &Config) -> MyResult<()> {
+    let file = open(in_file)?;
+    let target = fs::canonicalize(out_file)?;
+    let dir = target.parent().unwrap_or_else(|| Path::new("."));
+    let tmp = NamedTempFile::new_in(dir)?;
+    tmp.as_file()
+        .set_permissions(fs::metadata(&target)?.permissions())?;
+
+    uniq(file, Box::new(tmp.reopen()?), config)?;
+    tmp.persist(&target)?;
+    Ok(())
+}
+
+// Feeds `input` to `parser` and tells whether it reaches the end of a record.
+fn ends_record(parser: &mut csv_core::Reader, mut input: &[u8]) -> bool {
+    // The fields themselves are not needed.
+    let mut output = [0; 1024];
+    let mut ends = [0; 64];
+    loop {
+        let (result, read, _, _) = parser.read_record(input, &mut output, &mut ends);
+        input = &input[read..];
+        match result {
+            ReadRecordResult::InputEmpty => return false,
+            ReadRecordResult::Record | ReadRecordResult::End => return true,
+            ReadRecordResult::OutputFull | ReadRecordResult::OutputEndsFull => {}
+        }
+    }
+}
 
-    let mut file = open(&config.in_file).map_err(|e| format!("{}: {}", config.in_file, e))?;
-    let mut line = String::new();
+fn uniq(mut file: Box<dyn BufRead>, writer: Box<dyn Write>, config: &Config) -> MyResult<()> {
+    let mut output = Output::new(writer, config)?;
+
+    if config.is_frequency() {
+        let entries = count_frequencies(file, config)?;
+        let limit = config.top.unwrap_or(entries.len());
+        for (line, cnt) in entries.iter().take(limit) {
+            output.write(line, *cnt)?;
+        }
+        return output.flush();
+    }
+
+    let mut line = Vec::new();
     let mut line_count = 0;
-    let mut current: Option<String> = None;
+    // first line of the current group, its key, and the most recent line
+    let mut first = Vec::new();
+    let mut first_key = Vec::new();
+    let mut last = Vec::new();
 
     loop {
-        let bytes = file.read_line(&mut line)?;
-
-        if let Some(ref cur) = current {
-            let newline_delimiters = ['\r', '\n'];
-            if cur.trim_end_matches(newline_delimiters) != line.trim_end_matches(newline_delimiters)
-                || bytes == 0
-            {
-                write(cur, line_count, &config)?;
-                line_count = 0;
-                current = Some(line.clone());
-            }
-        } else {
-            // initialize
-            current = Some(line.clone());
+        let bytes = config.read_line(&mut file, &mut line)?;
+
+        if line_count > 0 && (bytes == 0 || config.key(&line) != first_key.as_slice()) {
+            let kept = match config.keep {
+                Keep::First => &first,
+                Keep::Last => &last,
+            };
+            output.write(kept, line_count)?;
+            line_count = 0;
         }
 
         if bytes == 0 {
             break;
         }
 
+        if line_count == 0 {
+            first_key = config.key(&line).into_owned();
+            first.clone_from(&line);
+        }
         line_count += 1;
+        std::mem::swap(&mut last, &mut line);
         line.clear();
     }
 
-    Ok(())
+    output.flush()
+}
+
+enum Output {
+    Text {
+        writer: BufWriter<Box<dyn Write>>,
+        count_format: Option<CountFormat>,
+    },
+    Json {
+        writer: BufWriter<Box<dyn Write>>,
+        strict_eol: bool,
+    },
+    Csv {
+        writer: Box<csv::Writer<Box<dyn Write>>>,
+        strict_eol: bool,
+    },
+}
+
+impl Output {
+    fn new(writer: Box<dyn Write>, config: &Config) -> MyResult<Self> {
+        let output = match config.format {
+            Format::Text => Output::Text {
+                writer: BufWriter::new(writer),
+                count_format: match config.count_format {
+                    Some(ref count_format) => Some(count_format.clone()),
+                    None if config.count || config.is_frequency() => Some(CountFormat::default()),
+                    None => None,
+                },
+            },
+            Format::Json => Output::Json {
+                writer: BufWriter::new(writer),
+                strict_eol: config.strict_eol,
+            },
+            Format::Csv => {
+                let mut writer = csv::Writer::from_writer(writer);
+                writer.write_record(["count", "line"])?;
+                Output::Csv {
+                    writer: Box::new(writer),
+                    strict_eol: config.strict_eol,
+                }
+            }
+        };
+        Ok(output)
+    }
+
+    // Text output keeps the original line ending; JSON and CSV records hold
+    // the line without its terminator.
+    fn write(&mut self, line: &[u8], cnt: usize) -> MyResult<()> {
+        match self {
+            Output::Text {
+                writer,
+                count_format: Some(count_format),
+            } => count_format.write(writer, line, cnt)?,
+            Output::Text {
+                writer,
+                count_format: None,
+            } => {
+                writer.write_all(line)?;
+                if !line.ends_with(b"\n") {
+                    writer.write_all(b"\n")?;
+                }
+            }
+            Output::Json { writer, strict_eol } => {
+                // A line that is not valid UTF-8 is given in base64 as
+                // "bytes" rather than as "line", so that no byte is lost.
+                let line = trim_eol(line, *strict_eol);
+                let record = match std::str::from_utf8(line) {
+                    Ok(line) => serde_json::json!({ "count": cnt, "line": line }),
+                    Err(_) => serde_json::json!({
+                        "count": cnt,
+                        "bytes": BASE64_STANDARD.encode(line),
+                    }),
+                };
+                writeln!(writer, "{}", record)?;
+            }
+            Output::Csv { writer, strict_eol } => {
+                writer.write_record([cnt.to_string().as_bytes(), trim_eol(line, *strict_eol)])?;
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> MyResult<()> {
+        match self {
+            Output::Text { writer, .. } | Output::Json { writer, .. } => writer.flush()?,
+            Output::Csv { writer, .. } => writer.flush()?,
+        }
+        Ok(())
+    }
+}
+
+// Counts every distinct line regardless of adjacency. Lines are returned in
+// descending order of count; ties keep the order in which they were first seen.
+fn count_frequencies(mut file: impl BufRead, config: &Config) -> MyResult<Vec<(Vec<u8>, usize)>> {
+    let mut index: HashMap<Vec<u8>, usize> = HashMap::new();
+    let mut entries: Vec<(Vec<u8>, usize)> = Vec::new();
+    let mut line = Vec::new();
+
+    while config.read_line(&mut file, &mut line)? > 0 {
+        let key = config.key(&line);
+        match index.get(key.as_ref()) {
+            Some(&i) => {
+                entries[i].1 += 1;
+                if config.keep == Keep::Last {
+                    entries[i].0.clone_from(&line);
+                }
+            }
+            None => {
+                index.insert(key.into_owned(), entries.len());
+                entries.push((line.clone(), 1));
+            }
+        }
+        line.clear();
+    }
+
+    entries.sort_by_key(|(_, cnt)| Reverse(*cnt));
+    Ok(entries)
 }
 
 fn open(filename: &str) -> MyResult<Box<dyn BufRead>> {
@@ -77,3 +525,14 @@ fn open(filename: &str) -> MyResult<Box<dyn BufRead>> {
         _ => Ok(Box::new(BufReader::new(File::open(filename)?))),
     }
 }
+
+// Strips the line terminator used for comparison. `\r\n` and `\n` are
+// considered equal unless `strict` is set, in which case only `\n` is removed.
+fn trim_eol(line: &[u8], strict: bool) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    if strict {
+        line
+    } else {
+        line.strip_suffix(b"\r").unwrap_or(line)
+    }
+}
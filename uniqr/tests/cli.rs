@@ -80,6 +80,12 @@ const T6: Test = Test {
     out_count: "tests/expected/t6.txt.c.out",
 };
 
+const CRLF: Test = Test {
+    input: "tests/inputs/crlf.txt",
+    out: "tests/expected/crlf.txt.out",
+    out_count: "tests/expected/crlf.txt.c.out",
+};
+
 // --------------------------------------------------
 fn gen_bad_file() -> String {
     loop {
@@ -617,3 +623,305 @@ fn t6_outfile_count() -> Result<()> {
 fn t6_stdin_outfile_count() -> Result<()> {
     run_stdin_outfile_count(&T6)
 }
+
+// --------------------------------------------------
+#[test]
+fn crlf() -> Result<()> {
+    run(&CRLF)
+}
+
+#[test]
+fn crlf_count() -> Result<()> {
+    run_count(&CRLF)
+}
+
+// --------------------------------------------------
+#[test]
+fn crlf_strict_eol_count() -> Result<()> {
+    let expected = fs::read_to_string("tests/expected/crlf.txt.strict.c.out")?;
+    let output = Command::cargo_bin(PRG)?
+        .args(["tests/inputs/crlf.txt", "-c", "--strict-eol"])
+        .output()
+        .expect("fail");
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).expect("invalid UTF-8");
+    assert_eq!(stdout, expected);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn invalid_utf8() -> Result<()> {
+    let input: &[u8] = b"\xff\xfe\n\xff\xfe\n\x00\x01";
+    let output = Command::cargo_bin(PRG)?
+        .arg("-c")
+        .write_stdin(input)
+        .output()
+        .expect("fail");
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"      2 \xff\xfe\n      1 \x00\x01\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+fn run_args(args: &[&str], expected_file: &str) -> Result<()> {
+    let expected = fs::read_to_string(expected_file)?;
+    let output = Command::cargo_bin(PRG)?.args(args).output().expect("fail");
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).expect("invalid UTF-8");
+    assert_eq!(stdout, expected);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn three_frequency() -> Result<()> {
+    run_args(
+        &[THREE.input, "--frequency"],
+        "tests/expected/three.txt.frequency.out",
+    )
+}
+
+#[test]
+fn three_top() -> Result<()> {
+    run_args(
+        &[THREE.input, "--top", "2"],
+        "tests/expected/three.txt.top2.out",
+    )
+}
+
+#[test]
+fn three_frequency_json() -> Result<()> {
+    run_args(
+        &[THREE.input, "--frequency", "--format", "json"],
+        "tests/expected/three.txt.frequency.json",
+    )
+}
+
+#[test]
+fn three_frequency_csv() -> Result<()> {
+    run_args(
+        &[THREE.input, "--frequency", "--format", "csv"],
+        "tests/expected/three.txt.frequency.csv",
+    )
+}
+
+#[test]
+fn three_csv() -> Result<()> {
+    run_args(
+        &[THREE.input, "--format", "csv"],
+        "tests/expected/three.txt.csv",
+    )
+}
+
+#[test]
+fn three_count_format() -> Result<()> {
+    run_args(
+        &[THREE.input, "--count-format", r"{count}\t{line}"],
+        "tests/expected/three.txt.tsv.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_count_format() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args([THREE.input, "--count-format", "{cnt} {line}"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(r#"unknown placeholder "{cnt}""#));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn non_utf8_json() -> Result<()> {
+    // "caf\xe9" in base64, while valid lines keep "line"
+    Command::cargo_bin(PRG)?
+        .args(["--frequency", "--format", "json"])
+        .write_stdin(b"caf\xe9\nok\ncaf\xe9\n".as_slice())
+        .assert()
+        .success()
+        .stdout("{\"bytes\":\"Y2Fm6Q==\",\"count\":2}\n{\"count\":1,\"line\":\"ok\"}\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn non_utf8_csv() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args(["--format", "csv"])
+        .write_stdin(b"caf\xe9\ncaf\xe9\n".as_slice())
+        .assert()
+        .success()
+        .stdout(b"count,line\n2,caf\xe9\n".as_slice());
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn wide_count() -> Result<()> {
+    let input = "a\n".repeat(12345);
+    Command::cargo_bin(PRG)?
+        .arg("-c")
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout("  12345 a\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn csv_key_field() -> Result<()> {
+    run_args(
+        &["tests/inputs/people.csv", "--key-fields", "2"],
+        "tests/expected/people.csv.key2.out",
+    )
+}
+
+#[test]
+fn csv_key_field_keep_last() -> Result<()> {
+    run_args(
+        &[
+            "tests/inputs/people.csv",
+            "--key-fields",
+            "2",
+            "--keep",
+            "last",
+        ],
+        "tests/expected/people.csv.key2.last.out",
+    )
+}
+
+#[test]
+fn csv_key_fields_count() -> Result<()> {
+    run_args(
+        &["tests/inputs/people.csv", "--key-fields", "2,3", "-c"],
+        "tests/expected/people.csv.key23.c.out",
+    )
+}
+
+#[test]
+fn tsv_key_field() -> Result<()> {
+    run_args(
+        &[
+            "tests/inputs/fields.tsv",
+            "--key-fields",
+            "2",
+            "--delimiter",
+            "\t",
+        ],
+        "tests/expected/fields.tsv.key2.out",
+    )
+}
+
+#[test]
+fn csv_key_field_multiline() -> Result<()> {
+    // A quoted field may hold newlines, which do not end the record
+    let input = "1,\"a\nb\",x\n2,\"a\nb\",y\n3,\"a\",z\n";
+    Command::cargo_bin(PRG)?
+        .args(["--key-fields", "2", "-c"])
+        .write_stdin(input)
+        .assert()
+        .success()
+        .stdout("      2 1,\"a\nb\",x\n      1 3,\"a\",z\n");
+    Ok(())
+}
+
+#[test]
+fn dies_bad_key_field() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args([THREE.input, "--key-fields", "0"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(r#"illegal field value: "0""#));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn same_input_and_output() -> Result<()> {
+    let file = NamedTempFile::new()?;
+    fs::copy(THREE.input, file.path())?;
+    let path = file.path().to_str().unwrap();
+
+    Command::cargo_bin(PRG)?
+        .args([path, path])
+        .assert()
+        .success()
+        .stdout("");
+
+    let expected = fs::read_to_string(THREE.out)?;
+    assert_eq!(fs::read_to_string(path)?, expected);
+    Ok(())
+}
+
+#[test]
+fn same_stdin_and_output() -> Result<()> {
+    let file = NamedTempFile::new()?;
+    fs::copy(THREE.input, file.path())?;
+
+    Command::cargo_bin(PRG)?
+        .args(["-".as_ref(), file.path()])
+        .pipe_stdin(file.path())?
+        .assert()
+        .success()
+        .stdout("");
+
+    let expected = fs::read_to_string(THREE.out)?;
+    assert_eq!(fs::read_to_string(file.path())?, expected);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn in_place() -> Result<()> {
+    let file1 = NamedTempFile::new()?;
+    let file2 = NamedTempFile::new()?;
+    fs::copy(THREE.input, file1.path())?;
+    fs::copy(T5.input, file2.path())?;
+
+    Command::cargo_bin(PRG)?
+        .args(["--in-place", "-c"])
+        .args([file1.path(), file2.path()])
+        .assert()
+        .success()
+        .stdout("");
+
+    assert_eq!(
+        fs::read_to_string(file1.path())?,
+        fs::read_to_string(THREE.out_count)?
+    );
+    assert_eq!(
+        fs::read_to_string(file2.path())?,
+        fs::read_to_string(T5.out_count)?
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_in_place_stdin() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .arg("--in-place")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "--in-place cannot be used with standard input",
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_too_many_files() -> Result<()> {
+    Command::cargo_bin(PRG)?
+        .args([ONE.input, TWO.input, THREE.input])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("use --in-place"));
+    Ok(())
+}